@@ -1,11 +1,17 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use anyhow::Result as AnyResult;
+use fastnbt::Value;
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
 use ignore::overrides::{Override, OverrideBuilder};
 use path_absolutize::Absolutize;
 use serde::{Deserialize, Deserializer};
 
 use crate::entries::ExtraEntry;
+use crate::formats::{snbt, BlockMatcher, BlockState, Compound, NbtPath, Scheme};
 use crate::utils;
 
 #[derive(Debug, Deserialize)]
@@ -16,28 +22,384 @@ pub struct Config {
     pub dirname: Option<PathBuf>,
     #[serde(default, deserialize_with = "deserialize_extra_entries")]
     pub extra_entries: Vec<ExtraEntry>,
-    #[serde(default, deserialize_with = "deserialize_resourcepack")]
-    pub resourcepack: Option<PathBuf>,
+    #[serde(default, alias = "resourcepacks", deserialize_with = "deserialize_resourcepacks")]
+    pub resourcepack: Vec<PathBuf>,
     #[serde(default)]
     pub reset_player: bool,
     #[serde(default)]
+    pub level: LevelSettings,
+    #[serde(default)]
+    pub players: PlayerSettings,
+    #[serde(default)]
+    pub data: DataSettings,
+    #[serde(default)]
     pub zip_datapacks: bool,
     #[serde(default)]
     pub clean_chunks: bool,
-    #[serde(default = "ignored_blocks")]
-    pub ignored_blocks: Vec<String>,
+    #[serde(default)]
+    pub audit_chunks: bool,
+    #[serde(default)]
+    pub recompress_regions: Option<Recompression>,
+    #[serde(default)]
+    pub corrupt_chunks: CorruptPolicy,
+    #[serde(default = "ignored_blocks", rename = "ignored_blocks")]
+    ignored_block_patterns: Vec<String>,
+    #[serde(skip)]
+    pub ignored_blocks: BlockMatcher,
+    #[serde(default)]
+    pub block_tags: HashMap<String, Vec<String>>,
+    #[serde(default = "ignored_biomes", deserialize_with = "deserialize_globset")]
+    pub ignored_biomes: GlobSet,
+    #[serde(default)]
+    pub biome_override: Option<BiomeOverride>,
+    #[serde(default)]
+    pub removed_entities: Vec<EntityFilter>,
+    #[serde(default, deserialize_with = "deserialize_globset")]
+    pub removed_block_entities: GlobSet,
+    #[serde(default)]
+    pub cleared_containers: Vec<BlockEntityFilter>,
+    #[serde(default)]
+    pub strip_command_output: bool,
+    #[serde(default)]
+    pub strip_chunk_data: StripSettings,
+    #[serde(default, deserialize_with = "deserialize_block_map")]
+    pub replace_blocks: Vec<(BlockState, BlockState)>,
     #[serde(default, deserialize_with = "deserialize_globset")]
     pub accepted_scores: GlobSet,
     #[serde(default, deserialize_with = "deserialize_globset")]
     pub accepted_objectives: GlobSet,
+    #[serde(default, deserialize_with = "deserialize_globset_map")]
+    pub accepted_objective_scores: Vec<(GlobMatcher, GlobSet)>,
+    #[serde(default, deserialize_with = "deserialize_globset")]
+    pub accepted_teams: GlobSet,
+    #[serde(default)]
+    pub set_scores: BTreeMap<String, BTreeMap<String, Option<i32>>>,
+    #[serde(default, deserialize_with = "deserialize_globset")]
+    pub accepted_storages: GlobSet,
+    #[serde(default, deserialize_with = "deserialize_globset")]
+    pub rejected_storages: GlobSet,
+    #[serde(default, deserialize_with = "deserialize_snbt_map")]
+    pub set_storages: Vec<(String, Value)>,
+    #[serde(default)]
+    pub nbt_patches: Vec<NbtPatch>,
     #[serde(deserialize_with = "deserialize_override")]
     pub accepted_entries: Override,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct LevelSettings {
+    #[serde(default, deserialize_with = "deserialize_game_rules")]
+    pub game_rules: HashMap<String, String>,
+    #[serde(default)]
+    pub game_type: Option<GameType>,
+    #[serde(default)]
+    pub difficulty: Option<Difficulty>,
+    #[serde(default)]
+    pub difficulty_locked: Option<bool>,
+    #[serde(default)]
+    pub allow_commands: Option<bool>,
+    #[serde(default)]
+    pub spawn: Option<[i32; 3]>,
+    #[serde(default)]
+    pub day_time: Option<i64>,
+    #[serde(default)]
+    pub time: Option<i64>,
+    #[serde(default)]
+    pub clear_weather: bool,
+    #[serde(default)]
+    pub seed: Option<Seed>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Seed {
+    Fixed(i64),
+    Random,
+}
+
+impl Seed {
+    pub fn value(&self) -> i64 {
+        match self {
+            Self::Fixed(seed) => *seed,
+            Self::Random => rand::random(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Seed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::Number(seed) => Self::Fixed(seed.as_i64().ok_or_else(|| {
+                serde::de::Error::custom("the seed must be a 64-bit integer")
+            })?),
+            serde_yaml::Value::String(seed) if seed == "random" => Self::Random,
+            // Text seeds are hashed the same way minecraft does
+            serde_yaml::Value::String(seed) => Self::Fixed(seed.parse().unwrap_or_else(|_| {
+                seed.encode_utf16().fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32)) as i64
+            })),
+            _ => return Err(serde::de::Error::custom("the seed must be a number, a text or \"random\"")),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameType {
+    Survival = 0,
+    Creative = 1,
+    Adventure = 2,
+    Spectator = 3,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Peaceful = 0,
+    Easy = 1,
+    Normal = 2,
+    Hard = 3,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerSettings {
+    #[serde(default)]
+    pub playerdata: PlayerFiles,
+    #[serde(default)]
+    pub stats: PlayerFiles,
+    #[serde(default)]
+    pub advancements: PlayerFiles,
+    #[serde(default, deserialize_with = "deserialize_uuids")]
+    pub whitelist: Vec<String>,
+    #[serde(skip, default = "rand::random")]
+    salt: u64,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            playerdata: PlayerFiles::default(),
+            stats: PlayerFiles::default(),
+            advancements: PlayerFiles::default(),
+            whitelist: vec![],
+            salt: rand::random(),
+        }
+    }
+}
+
+impl PlayerSettings {
+    /// Get a random uuid (version 4) that is stable for the whole packaging.
+    pub fn anonymize(&self, uuid: &str) -> u128 {
+        let hash = |seed: u8| {
+            let mut hasher = DefaultHasher::new();
+            (self.salt, seed, uuid.to_lowercase()).hash(&mut hasher);
+            hasher.finish() as u128
+        };
+        let uuid = hash(0) << 64 | hash(1);
+
+        uuid & !(0xf << 76) & !(0x3 << 62) | (0x4 << 76) | (0x2 << 62)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerFiles {
+    #[default]
+    Keep,
+    Remove,
+    Anonymize,
+}
+
+/// Entity filter, all the given criteria must match (at least one is required).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntityFilter {
+    #[serde(default, deserialize_with = "deserialize_glob")]
+    pub id: Option<GlobMatcher>,
+    #[serde(default, deserialize_with = "deserialize_glob")]
+    pub tag: Option<GlobMatcher>,
+    #[serde(default)]
+    pub custom_name: Option<bool>,
+}
+
+impl EntityFilter {
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none() && self.tag.is_none() && self.custom_name.is_none()
+    }
+
+    pub fn matches(&self, entity: &Compound) -> bool {
+        self.id.as_ref().is_none_or(|id| match entity.get("id") {
+            Some(Value::String(value)) => id.is_match(value),
+            _ => false,
+        }) && self.tag.as_ref().is_none_or(|tag| match entity.get("Tags") {
+            Some(Value::List(tags)) => tags.iter().any(|value| match value {
+                Value::String(value) => tag.is_match(value),
+                _ => false,
+            }),
+            _ => false,
+        }) && self.custom_name.is_none_or(|custom_name| {
+            entity.contains_key("CustomName") == custom_name
+        })
+    }
+}
+
+/// Block entity filter, all the given criteria must match (at least one is required).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockEntityFilter {
+    #[serde(default, deserialize_with = "deserialize_glob")]
+    pub id: Option<GlobMatcher>,
+    #[serde(default)]
+    pub pos: Option<[i32; 3]>,
+}
+
+impl BlockEntityFilter {
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none() && self.pos.is_none()
+    }
+
+    pub fn matches(&self, entity: &Compound) -> bool {
+        self.id.as_ref().is_none_or(|id| match entity.get("id") {
+            Some(Value::String(value)) => id.is_match(value),
+            _ => false,
+        }) && self.pos.is_none_or(|pos| {
+            ["x", "y", "z"].iter().zip(pos).all(|(axis, pos)| {
+                entity.get(*axis).is_some_and(|value| value == &Value::Int(pos))
+            })
+        })
+    }
+}
+
+/// Biome forced in every chunk that is not inside the excepted area.
+#[derive(Debug, Deserialize)]
+pub struct BiomeOverride {
+    pub biome: String,
+    #[serde(default)]
+    pub except: Option<Area>,
+}
+
+/// Horizontal area in block coordinates (both corners included).
+#[derive(Debug, Deserialize)]
+pub struct Area {
+    pub from: [i32; 2],
+    pub to: [i32; 2],
+}
+
+impl Area {
+    /// Check if the chunk (in chunk coordinates) overlaps the area.
+    pub fn overlaps_chunk(&self, x: i32, z: i32) -> bool {
+        let (min_x, max_x) = (self.from[0].min(self.to[0]) >> 4, self.from[0].max(self.to[0]) >> 4);
+        let (min_z, max_z) = (self.from[1].min(self.to[1]) >> 4, self.from[1].max(self.to[1]) >> 4);
+        (min_x..=max_x).contains(&x) && (min_z..=max_z).contains(&z)
+    }
+}
+
+/// What to do with chunks that cannot be read or parsed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CorruptPolicy {
+    /// Remove the chunk from the region.
+    Skip,
+    /// Keep the original chunk bytes.
+    Keep,
+    /// Leave the whole region out of the package.
+    #[default]
+    Abort,
+}
+
+/// Compression of the chunks when rewriting region files.
+#[derive(Debug, Deserialize)]
+pub struct Recompression {
+    /// Keep the original scheme of each chunk when not set.
+    #[serde(default)]
+    pub compression: Option<Scheme>,
+    /// Keep the original payload of each chunk when not set (unless the scheme changes).
+    #[serde(default)]
+    pub level: Option<u32>,
+}
+
+impl Recompression {
+    pub const DEFAULT_LEVEL: u32 = 6;
+}
+
+/// Chunk data that can be stripped from regions.
+#[derive(Debug, Default, Deserialize)]
+pub struct StripSettings {
+    #[serde(default)]
+    pub light: bool,
+    #[serde(default)]
+    pub heightmaps: bool,
+    #[serde(default)]
+    pub inhabited_time: bool,
+    #[serde(default)]
+    pub ticks: bool,
+    #[serde(default)]
+    pub post_processing: bool,
+    #[serde(default)]
+    pub structures: bool,
+    #[serde(default)]
+    pub carving_masks: bool,
+}
+
+impl StripSettings {
+    pub fn any(&self) -> bool {
+        self.light
+            || self.heightmaps
+            || self.inhabited_time
+            || self.ticks
+            || self.post_processing
+            || self.structures
+            || self.carving_masks
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DataSettings {
+    #[serde(default)]
+    pub remove_unreferenced_maps: bool,
+    #[serde(default)]
+    pub clear_raids: bool,
+    #[serde(default)]
+    pub reset_random_sequences: bool,
+    #[serde(default)]
+    pub remove_forced_chunks: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NbtPatch {
+    #[serde(deserialize_with = "deserialize_globset")]
+    pub files: GlobSet,
+    #[serde(default)]
+    pub remove: Vec<NbtPath>,
+    #[serde(default, deserialize_with = "deserialize_snbt_map")]
+    pub set: Vec<(NbtPath, Value)>,
+    #[serde(default, deserialize_with = "deserialize_snbt_map")]
+    pub merge: Vec<(NbtPath, Value)>,
+}
+
+impl NbtPatch {
+    /// Apply the removals, then the new values and finally the merges.
+    pub fn apply(&self, nbt: &mut Value) -> AnyResult<()> {
+        for path in &self.remove {
+            path.remove(nbt);
+        }
+        for (path, value) in &self.set {
+            path.set(nbt, value.to_owned())?;
+        }
+        for (path, value) in &self.merge {
+            path.merge(nbt, value.to_owned())?;
+        }
+
+        Ok(())
+    }
+}
+
 fn ignored_blocks() -> Vec<String> {
     vec!["minecraft:air".to_owned()]
 }
 
+fn ignored_biomes() -> GlobSet {
+    build_globset(vec!["minecraft:the_void".to_owned()]).expect("valid default biome pattern")
+}
+
 fn deserialize_extra_entries<'de, D>(deserializer: D) -> Result<Vec<ExtraEntry>, D::Error>
 where
     D: Deserializer<'de>,
@@ -50,26 +412,117 @@ where
     }).collect())
 }
 
-fn deserialize_resourcepack<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> From<OneOrMany<T>> for Vec<T> {
+    fn from(value: OneOrMany<T>) -> Self {
+        match value {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+fn deserialize_resourcepacks<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let paths: Option<OneOrMany<PathBuf>> = Deserialize::deserialize(deserializer)?;
+    Ok(paths.map(Vec::from).unwrap_or_default().iter().filter_map(|path| {
+        path.canonicalize().map_err(|err| {
+            log::warn!("could not read resourcepack ({err})")
+        }).ok()
+    }).collect())
+}
+
+fn deserialize_game_rules<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rules: HashMap<String, serde_yaml::Value> = Deserialize::deserialize(deserializer)?;
+    rules.into_iter().map(|(rule, value)| Ok(match value {
+        serde_yaml::Value::Bool(value) => (rule, value.to_string()),
+        serde_yaml::Value::Number(value) => (rule, value.to_string()),
+        serde_yaml::Value::String(value) => (rule, value),
+        _ => return Err(serde::de::Error::custom(format!("invalid value for game rule {rule}"))),
+    })).collect()
+}
+
+fn deserialize_snbt_map<'de, D, K>(deserializer: D) -> Result<Vec<(K, Value)>, D::Error>
 where
     D: Deserializer<'de>,
+    K: std::str::FromStr,
+    K::Err: std::fmt::Display,
 {
-    let path: Option<PathBuf> = Deserialize::deserialize(deserializer)?;
-    Ok(path.and_then(|path| path.canonicalize().map_err(|err| {
-        log::warn!("could not read resourcepack ({err})")
-    }).ok()))
+    let values: BTreeMap<String, serde_yaml::Value> = Deserialize::deserialize(deserializer)?;
+    values.into_iter().map(|(key, value)| {
+        let value = match value {
+            serde_yaml::Value::String(value) => snbt::parse(&value),
+            serde_yaml::Value::Bool(value) => snbt::parse(&value.to_string()),
+            serde_yaml::Value::Number(value) => snbt::parse(&value.to_string()),
+            _ => Err(anyhow::anyhow!("values must be written as snbt strings")),
+        }.map_err(|err| serde::de::Error::custom(format!("{err} (at {key})")))?;
+        Ok((key.parse().map_err(serde::de::Error::custom)?, value))
+    }).collect()
+}
+
+fn deserialize_block_map<'de, D>(deserializer: D) -> Result<Vec<(BlockState, BlockState)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let blocks: BTreeMap<String, String> = Deserialize::deserialize(deserializer)?;
+    blocks.iter().map(|(from, to)| Ok((
+        from.parse().map_err(serde::de::Error::custom)?,
+        to.parse().map_err(serde::de::Error::custom)?,
+    ))).collect()
+}
+
+fn deserialize_uuids<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let uuids: Vec<String> = Deserialize::deserialize(deserializer)?;
+    Ok(uuids.iter().map(|uuid| uuid.to_lowercase()).collect())
 }
 
 fn deserialize_globset<'de, D>(deserializer: D) -> Result<GlobSet, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let patterns: Vec<String> = Deserialize::deserialize(deserializer)?;
+    let patterns: OneOrMany<String> = Deserialize::deserialize(deserializer)?;
+    build_globset(patterns.into()).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_glob<'de, D>(deserializer: D) -> Result<Option<GlobMatcher>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern: Option<String> = Deserialize::deserialize(deserializer)?;
+    pattern.map(|pattern| Ok(Glob::new(&pattern).map_err(serde::de::Error::custom)?.compile_matcher())).transpose()
+}
+
+fn deserialize_globset_map<'de, D>(deserializer: D) -> Result<Vec<(GlobMatcher, GlobSet)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let patterns: BTreeMap<String, OneOrMany<String>> = Deserialize::deserialize(deserializer)?;
+    patterns.into_iter().map(|(pattern, patterns)| Ok((
+        Glob::new(&pattern).map_err(serde::de::Error::custom)?.compile_matcher(),
+        build_globset(patterns.into()).map_err(serde::de::Error::custom)?,
+    ))).collect()
+}
+
+fn build_globset(patterns: Vec<String>) -> Result<GlobSet, globset::Error> {
     let mut globset = GlobSetBuilder::new();
     for pattern in patterns {
-        globset.add(Glob::new(&pattern).map_err(serde::de::Error::custom)?);
+        globset.add(Glob::new(&pattern)?);
     }
-    globset.build().map_err(serde::de::Error::custom)
+    globset.build()
 }
 
 fn deserialize_override<'de, D>(deserializer: D) -> Result<Override, D::Error>
@@ -84,7 +537,44 @@ where
     overrides.build().map_err(serde::de::Error::custom)
 }
 
+impl Default for Config {
+    /// Get the default config (see `config.yaml`).
+    fn default() -> Self {
+        Self::parse(include_str!("../config.yaml")).expect("the default config file is valid")
+    }
+}
+
 impl Config {
+    /// Check if region files need to be processed chunk by chunk.
+    pub fn process_regions(&self) -> bool {
+        self.clean_chunks || self.recompress_regions.is_some() || self.edit_chunks()
+    }
+
+    /// Check if chunks need to be parsed and edited.
+    pub fn edit_chunks(&self) -> bool {
+        !self.removed_entities.is_empty()
+            || !self.removed_block_entities.is_empty()
+            || !self.cleared_containers.is_empty()
+            || self.strip_command_output
+            || self.strip_chunk_data.any()
+            || !self.replace_blocks.is_empty()
+            || self.biome_override.is_some()
+    }
+
+    /// Check if the world file is accepted, its parent directories included (like the world walker).
+    pub fn is_accepted(&self, file: &Path) -> bool {
+        let file = file.strip_prefix("./").unwrap_or(file);
+        let mut dirs = file.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty());
+        !self.accepted_entries.matched(file, false).is_ignore()
+            && dirs.all(|dir| !self.accepted_entries.matched(dir, true).is_ignore())
+    }
+
+    /// Get the nbt patches matching the given world file.
+    pub fn nbt_patches_for<'a>(&'a self, file: &'a Path) -> impl Iterator<Item = &'a NbtPatch> {
+        let file = file.strip_prefix("./").unwrap_or(file);
+        self.nbt_patches.iter().filter(move |patch| patch.files.is_match(file))
+    }
+
     pub fn load(path: &Path, noprompt: bool) -> Option<Self> {
         let conf_path = path.absolutize().unwrap();
         let current_dir = std::env::current_dir().expect("could not get working dir");
@@ -113,7 +603,21 @@ impl Config {
     fn parse(contents: &str) -> Option<Self> {
         serde_yaml::from_str(contents).map_err(|err| {
             log::error!("could not parse the config file ({err})");
-        }).ok()
+        }).ok().and_then(|config: Self| config.compile().map_err(|err| {
+            log::error!("could not compile the config file ({err})");
+        }).ok())
+    }
+
+    /// Build the matchers that depend on several config fields.
+    fn compile(mut self) -> AnyResult<Self> {
+        if self.removed_entities.iter().any(EntityFilter::is_empty) {
+            anyhow::bail!("removed_entities filters need at least one criterion");
+        }
+        if self.cleared_containers.iter().any(BlockEntityFilter::is_empty) {
+            anyhow::bail!("cleared_containers filters need at least one criterion");
+        }
+        self.ignored_blocks = BlockMatcher::new(&self.ignored_block_patterns, &self.block_tags)?;
+        Ok(self)
     }
 
     fn edit(path: &Path, contents: &str) -> Option<Self> {
@@ -125,14 +629,48 @@ impl Config {
             log::error!("could not edit the config file ({err})");
             None
         }, |contents| {
-            Self::parse(&contents).map(|config| {
+            Self::parse(&contents).inspect(|_| {
                 if utils::confirm("Do you want to save the config file?", true) {
                     std::fs::write(path, &contents).unwrap_or_else(|err| {
                         log::error!("could not save the config file ({err})");
                     });
                 }
-                config
             }).or_else(|| Self::edit(path, &contents))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Option<Config> {
+        Config::parse(&format!("accepted_entries: []\n{contents}"))
+    }
+
+    #[test]
+    fn rejects_invalid_entity_filters() {
+        assert!(parse("removed_entities: [{id: minecraft:item}, {tag: debug, custom_name: false}]").is_some());
+        assert!(parse("removed_entities: [{}]").is_none());
+        assert!(parse("removed_entities: [{ID: minecraft:item}]").is_none());
+        assert!(parse("removed_entities: [{tags: debug}]").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_block_entity_filters() {
+        assert!(parse("cleared_containers: [{id: 'minecraft:*chest'}, {pos: [12, 64, -30]}]").is_some());
+        assert!(parse("cleared_containers: [{}]").is_none());
+        assert!(parse("cleared_containers: [{position: [12, 64, -30]}]").is_none());
+    }
+
+    #[test]
+    fn anonymizes_uuids_as_random_uuids() {
+        let players = PlayerSettings::default();
+        let uuid = players.anonymize("069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(uuid >> 76 & 0xf, 4, "version");
+        assert_eq!(uuid >> 62 & 0x3, 2, "variant");
+        assert_eq!(players.anonymize("069A79F4-44E9-4726-A5BE-FCA90E38AAF5"), uuid);
+        assert_ne!(players.anonymize("853c80ef-3c37-49fd-aa49-938b674adae6"), uuid);
+        assert_ne!(PlayerSettings::default().anonymize("069a79f4-44e9-4726-a5be-fca90e38aaf5"), uuid);
+    }
+}
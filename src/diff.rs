@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use fastnbt::Value;
+use rayon::prelude::*;
+
+use crate::formats::{NbtFormat, Region};
+use crate::storage::{self, ReadableStorage};
+
+/// Differences between two worlds (directories or zip archives), by file relative to the world root.
+#[derive(Default)]
+pub struct WorldDiff {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    /// Chunk differences of the modified region files.
+    pub chunks: BTreeMap<PathBuf, ChunkDiff>,
+}
+
+/// Differences between the chunks of two region files (in absolute chunk coordinates).
+#[derive(Default)]
+pub struct ChunkDiff {
+    pub added: Vec<(i32, i32)>,
+    pub removed: Vec<(i32, i32)>,
+    pub modified: Vec<(i32, i32)>,
+}
+
+impl ChunkDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Readable storage with the path of the world inside it (packaged worlds may be nested in `dirname`).
+struct World {
+    storage: Box<dyn ReadableStorage>,
+    root: PathBuf,
+}
+
+impl World {
+    fn open(path: &Path) -> Result<Self> {
+        let storage = storage::open(path).with_context(|| format!("could not open world [{}]", path.display()))?;
+        let root = storage.files()?.into_iter()
+            .filter(|file| file.file_name().is_some_and(|name| name == "level.dat"))
+            .min_by_key(|file| file.components().count())
+            .and_then(|file| file.parent().map(Path::to_owned))
+            .unwrap_or_default();
+
+        Ok(Self { storage, root })
+    }
+
+    /// List the files of the world relative to its root.
+    /// External chunk files are compared with their region.
+    fn files(&self) -> Result<BTreeSet<PathBuf>> {
+        Ok(self.storage.files()?.into_iter()
+            .filter(|file| file.extension().is_none_or(|ext| ext != "mcc"))
+            .filter_map(|file| file.strip_prefix(&self.root).ok().map(Path::to_owned))
+            .collect())
+    }
+
+    fn read(&self, file: &Path) -> Result<Vec<u8>> {
+        self.storage.read(&self.root.join(file))
+    }
+}
+
+impl WorldDiff {
+    pub fn run(old: &Path, new: &Path) -> Result<Self> {
+        let (old, new) = (World::open(old)?, World::open(new)?);
+        let (old_files, new_files) = (old.files()?, new.files()?);
+
+        let mut diff = Self {
+            added: new_files.difference(&old_files).cloned().collect(),
+            removed: old_files.difference(&new_files).cloned().collect(),
+            ..Default::default()
+        };
+
+        let common: Vec<_> = old_files.intersection(&new_files).collect();
+        let compared: Vec<_> = common.into_par_iter().map(|file| {
+            let changes = compare_file(&old, &new, file).unwrap_or_else(|err| {
+                log::warn!("could not compare file ({err:#}) [{}]", file.display());
+                Some(ChunkDiff::default())
+            });
+            (file, changes)
+        }).collect();
+
+        for (file, changes) in compared {
+            match changes {
+                None => {},
+                Some(chunks) => {
+                    diff.modified.push(file.to_owned());
+                    if !chunks.is_empty() {
+                        diff.chunks.insert(file.to_owned(), chunks);
+                    }
+                },
+            }
+        }
+
+        Ok(diff)
+    }
+
+    pub fn print(&self, old: &Path, new: &Path) {
+        println!(
+            "  {} {} ({}) with {} ({})",
+            console::style("Comparing").green().bold(),
+            old.file_name().unwrap_or_default().to_string_lossy(),
+            old.display(),
+            new.file_name().unwrap_or_default().to_string_lossy(),
+            new.display(),
+        );
+
+        for file in &self.added {
+            println!("   {}", console::style(format!("+ {}", file.display())).green());
+        }
+        for file in &self.removed {
+            println!("   {}", console::style(format!("- {}", file.display())).red());
+        }
+        for file in &self.modified {
+            println!("   {}", console::style(format!("~ {}", file.display())).yellow());
+            if let Some(chunks) = self.chunks.get(file) {
+                print_chunks("added", &chunks.added);
+                print_chunks("removed", &chunks.removed);
+                print_chunks("modified", &chunks.modified);
+            }
+        }
+
+        println!(
+            "   {} {} added, {} removed, {} modified",
+            console::style("Finished").green().bold(),
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len(),
+        );
+    }
+}
+
+/// Compare a file present in both worlds, returns `None` when unchanged.
+/// Region files are compared chunk by chunk (external chunks included) and nbt files by value
+/// (compression is ignored).
+fn compare_file(old: &World, new: &World, file: &Path) -> Result<Option<ChunkDiff>> {
+    let (old_bytes, new_bytes) = (old.read(file)?, new.read(file)?);
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("mca") => {
+            let (old_region, new_region) = (Region::from_bytes(old_bytes, file), Region::from_bytes(new_bytes, file));
+            // External chunks can change while the region stays the same
+            if old_region.bytes == new_region.bytes && !old_region.has_external_chunks() {
+                return Ok(None);
+            }
+            let chunks = compare_chunks(&read_chunks(old, old_region)?, &read_chunks(new, new_region)?);
+            Ok((!chunks.is_empty()).then_some(chunks))
+        },
+        _ if old_bytes == new_bytes => Ok(None),
+        Some("dat") => match (Value::from_bytes(&old_bytes), Value::from_bytes(&new_bytes)) {
+            (Ok(old), Ok(new)) if old == new => Ok(None),
+            _ => Ok(Some(ChunkDiff::default())),
+        },
+        _ => Ok(Some(ChunkDiff::default())),
+    }
+}
+
+/// Read the chunks of a region by absolute position (corrupt chunks have no value).
+fn read_chunks(world: &World, region: Region) -> Result<HashMap<(i32, i32), Option<Value>>> {
+    let mut chunks = HashMap::new();
+    for raw in region.chunks_with(|path| world.read(path))? {
+        let (position, value) = match raw {
+            Ok(raw) => (
+                region.absolute_position(raw.x, raw.z),
+                raw.decompress().and_then(|data| Ok(fastnbt::from_bytes(&data)?)).ok(),
+            ),
+            Err(chunk) => ((chunk.x, chunk.z), None),
+        };
+        chunks.insert(position, value);
+    }
+
+    Ok(chunks)
+}
+
+fn compare_chunks(old: &HashMap<(i32, i32), Option<Value>>, new: &HashMap<(i32, i32), Option<Value>>) -> ChunkDiff {
+    let mut diff = ChunkDiff::default();
+    for (position, value) in new {
+        match old.get(position) {
+            None => diff.added.push(*position),
+            Some(old) if old != value => diff.modified.push(*position),
+            Some(_) => {},
+        }
+    }
+    diff.removed.extend(old.keys().filter(|position| !new.contains_key(position)));
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.modified.sort();
+    diff
+}
+
+fn print_chunks(action: &str, chunks: &[(i32, i32)]) {
+    if !chunks.is_empty() {
+        let chunks: Vec<_> = chunks.iter().map(|(x, z)| format!("{x} {z}")).collect();
+        println!("       {action} chunks: {}", chunks.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::{nbt, ByteArray};
+
+    use super::*;
+    use crate::formats::anvil::{write_chunks, RawChunk, Scheme};
+    use crate::storage::{FilesystemStorage, Storage, ZipStorage};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mcwpack-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write(dir: &Path, file: &str, contents: &[u8]) {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// Write a region file (`r.0.0.mca`) with its external chunks in the given directory.
+    fn write_region(dir: &Path, chunks: &[(usize, usize, Value)]) {
+        let chunks: Vec<_> = chunks.iter().map(|(x, z, value)| {
+            RawChunk::compress(*x, *z, 0, &fastnbt::to_bytes(value).unwrap(), Scheme::Zlib, 6).unwrap()
+        }).collect();
+        let (bytes, external) = write_chunks(&chunks);
+        write(dir, "region/r.0.0.mca", &bytes);
+        for chunk in external {
+            write(dir, &format!("region/c.{}.{}.mcc", chunk.x, chunk.z), &chunk.payload);
+        }
+    }
+
+    /// Chunk too large for the region file (incompressible bytes).
+    fn external_chunk(seed: u32) -> Value {
+        let mut state = seed;
+        let bytes = (0..1 << 20).map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as i8
+        }).collect();
+        Value::Compound([("data".to_owned(), Value::ByteArray(ByteArray::new(bytes)))].into())
+    }
+
+    fn chunks(positions: &[((i32, i32), Option<i32>)]) -> HashMap<(i32, i32), Option<Value>> {
+        positions.iter().map(|(position, value)| (*position, value.map(Value::Int))).collect()
+    }
+
+    #[test]
+    fn compares_chunks_by_position() {
+        let old = chunks(&[((0, 0), Some(1)), ((1, 0), Some(1)), ((2, 0), None), ((3, 0), Some(1))]);
+        let new = chunks(&[((0, 0), Some(1)), ((1, 0), Some(2)), ((2, 0), None), ((-1, 4), Some(1))]);
+        let diff = compare_chunks(&old, &new);
+        assert_eq!(diff.added, [(-1, 4)]);
+        assert_eq!(diff.removed, [(3, 0)]);
+        assert_eq!(diff.modified, [(1, 0)]);
+        assert!(compare_chunks(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn detects_nested_world_roots() {
+        let dir = temp_dir("nested");
+        let zip = dir.join("world.zip");
+        let storage = ZipStorage::new(&zip);
+        storage.write(Path::new("pack/datapacks/demo/level.dat"), b"").unwrap();
+        storage.write(Path::new("pack/level.dat"), b"").unwrap();
+        storage.write(Path::new("pack/data/map_0.dat"), b"").unwrap();
+        drop(storage);
+
+        let world = World::open(&zip).unwrap();
+        assert_eq!(world.root, Path::new("pack"));
+        assert_eq!(world.files().unwrap(), BTreeSet::from([
+            PathBuf::from("data/map_0.dat"),
+            PathBuf::from("datapacks/demo/level.dat"),
+            PathBuf::from("level.dat"),
+        ]));
+
+        write(&dir, "flat/level.dat", b"");
+        assert_eq!(World::open(&dir.join("flat")).unwrap().root, Path::new(""));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compares_worlds() {
+        let dir = temp_dir("worlds");
+        let (old, new) = (dir.join("old"), dir.join("new"));
+        let level = nbt!({"Data": {"LevelName": "demo"}});
+        write(&old, "level.dat", &level.to_bytes().unwrap());
+        write(&new, "level.dat", &fastnbt::to_bytes(&level).unwrap());
+        write(&old, "data/removed.dat", b"");
+        write(&new, "data/added.dat", b"");
+        write(&old, "icon.png", b"old");
+        write(&new, "icon.png", b"new");
+        write_region(&old, &[(0, 0, nbt!({"a": 1})), (1, 0, nbt!({"a": 1})), (2, 0, nbt!({"a": 1}))]);
+        write_region(&new, &[(0, 0, nbt!({"a": 1})), (1, 0, nbt!({"a": 2})), (0, 1, nbt!({"a": 1}))]);
+
+        let diff = WorldDiff::run(&old, &new).unwrap();
+        assert_eq!(diff.added, [PathBuf::from("data/added.dat")]);
+        assert_eq!(diff.removed, [PathBuf::from("data/removed.dat")]);
+        assert_eq!(diff.modified, [PathBuf::from("icon.png"), PathBuf::from("region/r.0.0.mca")]);
+        let chunks = &diff.chunks[Path::new("region/r.0.0.mca")];
+        assert_eq!(chunks.added, [(0, 1)]);
+        assert_eq!(chunks.removed, [(2, 0)]);
+        assert_eq!(chunks.modified, [(1, 0)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compares_external_chunks() {
+        let dir = temp_dir("external");
+        let (old, new) = (dir.join("old"), dir.join("new"));
+        for world in [&old, &new] {
+            write(world, "level.dat", &nbt!({"Data": {}}).to_bytes().unwrap());
+        }
+        write_region(&old, &[(0, 0, nbt!({"a": 1})), (1, 0, external_chunk(1))]);
+        write_region(&new, &[(0, 0, nbt!({"a": 1})), (1, 0, external_chunk(2))]);
+        let region = |world: &Path| std::fs::read(world.join("region/r.0.0.mca")).unwrap();
+        assert_eq!(region(&old), region(&new));
+
+        let diff = WorldDiff::run(&old, &new).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.modified, [PathBuf::from("region/r.0.0.mca")]);
+        assert_eq!(diff.chunks[Path::new("region/r.0.0.mca")].modified, [(1, 0)]);
+
+        let diff = WorldDiff::run(&old, &old).unwrap();
+        assert!(diff.modified.is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
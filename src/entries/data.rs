@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use fastnbt::{LongArray, Value};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+
+use crate::formats::{self, ForcedChunks, NbtFormat, Raids, RandomSequences, Region};
+use crate::storage;
+use super::*;
+
+/// World data file from a dimension `data/` directory (maps, raids, forced chunks, ...).
+#[derive(Clone, Debug, Deref, From)]
+pub struct DataEntry(PathBuf);
+
+impl DataEntry {
+    pub fn is_data(name: &str) -> bool {
+        Self::map_id(name).is_some()
+            || matches!(name, "chunks.dat" | "raids.dat" | "raids_end.dat" | "random_sequences.dat")
+    }
+
+    fn map_id(name: &str) -> Option<i32> {
+        name.strip_prefix("map_")?.strip_suffix(".dat")?.parse().ok()
+    }
+}
+
+impl Packageable for DataEntry {}
+
+impl<S: Storage> Visitor<DataEntry> for Packager<S> {
+    fn visit(&self, entry: &DataEntry) -> Result<()> {
+        let settings = &self.config.data;
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+
+        match name.as_ref() {
+            "chunks.dat" if settings.remove_forced_chunks => {
+                let mut nbt = ForcedChunks::load(entry)?;
+                if nbt.data.forced.is_some() {
+                    nbt.data.forced = Some(LongArray::new(vec![]));
+                }
+                self.write_nbt(entry, entry, &nbt)
+            },
+            "raids.dat" | "raids_end.dat" if settings.clear_raids => {
+                let mut nbt = Raids::load(entry)?;
+                nbt.data.raids.clear();
+                self.write_nbt(entry, entry, &nbt)
+            },
+            "random_sequences.dat" if settings.reset_random_sequences => {
+                let mut nbt = RandomSequences::load(entry)?;
+                nbt.data.sequences.clear();
+                self.write_nbt(entry, entry, &nbt)
+            },
+            name => match DataEntry::map_id(name) {
+                Some(id) if settings.remove_unreferenced_maps
+                    && self.maps.get().and_then(Option::as_ref).is_some_and(|maps| !maps.contains(&id)) => Ok(()),
+                _ => FileEntry::from(entry.to_path_buf()).package(self),
+            },
+        }
+    }
+}
+
+impl<S: Storage> Packager<S> {
+    /// Scan the map ids referenced by items in the accepted world entries (regions, data files
+    /// and structures, including the ones in datapacks). Returns `None` if a file could not be scanned.
+    pub(crate) fn scan_referenced_maps(&self) -> Option<HashSet<i32>> {
+        let mut files = vec![];
+        let walker = WalkBuilder::new("./")
+            .git_ignore(false)
+            .overrides(self.config.accepted_entries.to_owned())
+            .same_file_system(true)
+            .build();
+        for entry in walker.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let scanned = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("mca" | "dat" | "nbt") => true,
+                Some("zip") => path.starts_with("./datapacks"),
+                _ => false,
+            };
+            if scanned && path.is_file() {
+                files.push(path.to_owned());
+            }
+        }
+
+        files.par_iter().map(|file| scan_maps(file).with_context(|| {
+            format!("could not scan map references [{}]", file.display())
+        })).collect::<Result<Vec<_>>>().map(|maps| {
+            maps.into_iter().flatten().collect()
+        }).map_err(|err| self.progress.suspend(|| {
+            log::warn!("{err:#}, unreferenced maps are kept");
+        })).ok()
+    }
+}
+
+fn scan_maps(file: &Path) -> Result<HashSet<i32>> {
+    let mut maps = HashSet::new();
+    let mut visitor = |compound: &formats::Compound| {
+        if compound.get("id").is_some_and(|id| id == "minecraft:filled_map") {
+            // Map ids are stored in the item components since 1.20.5, in the item tag
+            // since 1.13 and as the item damage before
+            let id = compound.get("components").and_then(|c| match c {
+                Value::Compound(c) => c.get("minecraft:map_id"),
+                _ => None,
+            }).or_else(|| compound.get("tag").and_then(|tag| match tag {
+                Value::Compound(tag) => tag.get("map"),
+                _ => None,
+            })).or_else(|| compound.get("Damage"));
+
+            match id {
+                Some(Value::Int(id)) => { maps.insert(*id); },
+                Some(Value::Short(id)) => { maps.insert(*id as i32); },
+                _ => {},
+            }
+        }
+    };
+
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("mca") if file.metadata()?.len() > 8192 => {
+            for raw in Region::load(file)?.chunks()? {
+                formats::walk_compounds(&fastnbt::from_bytes(&raw?.decompress()?)?, &mut visitor);
+            }
+        },
+        Some("mca") => {},
+        // Structures of zipped datapacks
+        Some("zip") => {
+            let datapack = storage::open(file)?;
+            for structure in datapack.files()?.iter().filter(|f| f.extension().is_some_and(|ext| ext == "nbt")) {
+                formats::walk_compounds(&Value::from_bytes(&datapack.read(structure)?)?, &mut visitor);
+            }
+        },
+        _ if DataEntry::map_id(&file.file_name().unwrap_or_default().to_string_lossy()).is_some() => {},
+        _ => formats::walk_compounds(&Value::load(file)?, &mut visitor),
+    }
+
+    Ok(maps)
+}
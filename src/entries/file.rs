@@ -1,4 +1,6 @@
-use crate::utils::PathUtils;
+use fastnbt::Value;
+
+use crate::formats::NbtFormat;
 use super::*;
 
 #[derive(Clone, Debug, Deref, From)]
@@ -8,6 +10,11 @@ impl Packageable for FileEntry {}
 
 impl<S: Storage> Visitor<FileEntry> for Packager<S> {
     fn visit(&self, entry: &FileEntry) -> Result<()> {
+        if entry.extension().is_some_and(|ext| ext == "dat")
+            && self.config.nbt_patches_for(entry).next().is_some() {
+            return self.write_nbt(entry, entry, &Value::load(entry)?);
+        }
+
         self.target.copy(entry, &entry.prefix(self.config.dirname.as_ref()))
     }
 }
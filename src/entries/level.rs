@@ -1,5 +1,7 @@
+use fastnbt::Value;
+
 use crate::formats::{Level, NbtFormat};
-use crate::utils::PathUtils;
+use crate::LevelSettings;
 use super::*;
 
 #[derive(Clone, Debug, Deref, From)]
@@ -24,7 +26,48 @@ impl<S: Storage> Visitor<LevelEntry> for Packager<S> {
                 }
             });
         };
+        apply_settings(&mut nbt, &self.config.level);
+        if let Some(seed) = self.config.level.seed {
+            if !nbt.set_seed(seed.value()) {
+                self.progress.suspend(|| {
+                    log::warn!("could not set the seed of a level without seed [{}]", entry.display())
+                });
+            }
+        }
 
-        self.target.write(&entry.prefix(self.config.dirname.as_ref()), &nbt.to_bytes()?)
+        self.write_nbt(entry, entry, &nbt)
+    }
+}
+
+fn apply_settings(nbt: &mut Level, settings: &LevelSettings) {
+    if !settings.game_rules.is_empty() {
+        let game_rules = nbt.data.game_rules.get_or_insert_with(Default::default);
+        for (rule, value) in &settings.game_rules {
+            game_rules.insert(rule.to_owned(), Value::String(value.to_owned()));
+        }
+    }
+    if let Some(game_type) = settings.game_type {
+        nbt.data.game_type = Some(game_type as i32);
+    }
+    if let Some(difficulty) = settings.difficulty {
+        nbt.data.difficulty = Some(difficulty as i8);
+    }
+    if let Some(locked) = settings.difficulty_locked {
+        nbt.data.difficulty_locked = Some(locked as i8);
+    }
+    if let Some(allow_commands) = settings.allow_commands {
+        nbt.data.allow_commands = Some(allow_commands as i8);
+    }
+    if let Some([x, y, z]) = settings.spawn {
+        nbt.set_spawn(x, y, z);
+    }
+    if settings.day_time.is_some() {
+        nbt.data.day_time = settings.day_time;
+    }
+    if settings.time.is_some() {
+        nbt.data.time = settings.time;
+    }
+    if settings.clear_weather {
+        nbt.clear_weather();
     }
 }
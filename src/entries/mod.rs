@@ -1,26 +1,39 @@
+pub use data::DataEntry;
 pub use datapack::DatapackEntry;
 pub use extra::ExtraEntry;
 pub use file::FileEntry;
 pub use level::LevelEntry;
+pub use player::PlayerEntry;
 pub use region::RegionEntry;
 pub use resourcepack::ResourcepackEntry;
 pub use scoreboard::ScoreboardEntry;
+pub use storage::StorageEntry;
 
+pub(crate) use region::{decide_chunks, dimension, read_forced_chunks, SiblingRegions};
+
+mod data;
 mod datapack;
 mod extra;
 mod file;
 mod level;
+mod player;
 mod region;
 mod resourcepack;
 mod scoreboard;
+mod storage;
 
 use std::path::{Path, PathBuf};
 
 use anyhow::{Result, Context};
 use derive_more::{Deref, From};
+use serde::Serialize;
 
+use crate::formats::NbtFormat;
 use crate::Packager;
 use crate::storage::Storage;
+use crate::utils::PathUtils;
+
+const PLAYER_DIRS: [&str; 3] = ["./playerdata", "./stats", "./advancements"];
 
 pub trait Visitor<T> {
     fn visit(&self, entry: &T) -> Result<()>;
@@ -33,25 +46,47 @@ pub trait Packageable: Sized {
 }
 
 pub enum Entry {
+    Data(self::data::DataEntry),
     Datapack(self::datapack::DatapackEntry),
     Extra(self::extra::ExtraEntry),
     File(self::file::FileEntry),
     Level(self::level::LevelEntry),
+    Player(self::player::PlayerEntry),
     Region(self::region::RegionEntry),
     Resourcepack(self::resourcepack::ResourcepackEntry),
     Scoreboard(self::scoreboard::ScoreboardEntry),
+    Storage(self::storage::StorageEntry),
 }
 
 impl Entry {
     pub fn path(&self) -> &Path {
         match self {
+            Self::Data(entry) => entry,
             Self::Datapack(entry) => entry,
             Self::Extra(entry) => entry,
             Self::File(entry) => entry,
             Self::Level(entry) => entry,
+            Self::Player(entry) => entry,
             Self::Region(entry) => entry,
-            Self::Resourcepack(entry) => entry,
+            Self::Resourcepack(entry) => entry.path(),
             Self::Scoreboard(entry) => entry,
+            Self::Storage(entry) => entry,
+        }
+    }
+
+    /// Get the kind of the entry (e.g. `region`, `datapack`).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Data(_) => "data",
+            Self::Datapack(_) => "datapack",
+            Self::Extra(_) => "extra",
+            Self::File(_) => "file",
+            Self::Level(_) => "level",
+            Self::Player(_) => "player",
+            Self::Region(_) => "region",
+            Self::Resourcepack(_) => "resourcepack",
+            Self::Scoreboard(_) => "scoreboard",
+            Self::Storage(_) => "storage",
         }
     }
 
@@ -60,11 +95,24 @@ impl Entry {
             Some(Entry::Datapack(path.to_owned().into()))
         } else if !path.is_file() {
             None
-        } else if path.extension().map_or(false, |ext| ext == "mca") {
+        } else if path.parent().is_some_and(|dir| PLAYER_DIRS.iter().any(|d| dir == Path::new(d))) {
+            Some(Entry::Player(path.to_owned().into()))
+        } else if path.extension().is_some_and(|ext| ext == "mca") {
             Some(Entry::Region(path.to_owned().into()))
-        } else if path.file_name().map_or(false, |name| name == "scoreboard.dat") {
+        } else if path.extension().is_some_and(|ext| ext == "mcc") {
+            // External chunks are packaged with their region
+            None
+        } else if path.file_name().is_some_and(|name| name == "scoreboard.dat") {
             Some(Entry::Scoreboard(path.to_owned().into()))
-        } else if path.file_name().map_or(false, |name| name == "level.dat") {
+        } else if path.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+            name.starts_with("command_storage_") && name.ends_with(".dat")
+        }) {
+            Some(Entry::Storage(path.to_owned().into()))
+        } else if path.parent().and_then(Path::file_name).is_some_and(|dir| dir == "data")
+            && path.file_name().is_some_and(|name| DataEntry::is_data(&name.to_string_lossy())) {
+            Some(Entry::Data(path.to_owned().into()))
+        } else if path.file_name().is_some_and(|name| name == "level.dat") {
             Some(Entry::Level(path.to_owned().into()))
         } else {
             Some(Entry::File(path.to_owned().into()))
@@ -74,16 +122,67 @@ impl Entry {
 
 impl Packageable for Entry {}
 
+impl<S: Storage> Packager<S> {
+    /// Write an nbt file into the storage, applying the nbt patches matching the entry.
+    fn write_nbt<T: NbtFormat + Serialize>(&self, entry: &Path, to: &Path, nbt: &T) -> Result<()> {
+        let to = to.to_path_buf().prefix(self.config.dirname.as_ref());
+        let mut patches = self.config.nbt_patches_for(entry).peekable();
+        if patches.peek().is_none() {
+            return self.target.write(&to, &nbt.to_bytes()?);
+        }
+
+        let mut value = fastnbt::to_value(nbt)?;
+        for patch in patches {
+            patch.apply(&mut value).with_context(|| "could not apply nbt patch")?;
+        }
+
+        self.target.write(&to, &value.to_bytes()?)
+    }
+}
+
 impl<S: Storage> Visitor<Entry> for Packager<S> {
     fn visit(&self, entry: &Entry) -> Result<()> {
         match entry {
+            Entry::Data(entry) => entry.package(self),
             Entry::Datapack(entry) => entry.package(self),
             Entry::Extra(entry) => entry.package(self),
             Entry::File(entry) => entry.package(self),
             Entry::Level(entry) => entry.package(self),
+            Entry::Player(entry) => entry.package(self),
             Entry::Region(entry) => entry.package(self),
             Entry::Resourcepack(entry) => entry.package(self),
             Entry::Scoreboard(entry) => entry.package(self),
+            Entry::Storage(entry) => entry.package(self),
         }
     }
 }
+
+/// Helpers to package world files written in a temporary directory.
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::storage::MemoryStorage;
+    use crate::{Config, Packager};
+
+    pub fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mcwpack-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    pub fn write(dir: &Path, file: &str, contents: &[u8]) {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    pub fn packager(config: Config, world: &Path) -> Packager<MemoryStorage> {
+        Packager::new(config, world.to_owned(), MemoryStorage::default())
+    }
+
+    /// Get the packaged files with their contents.
+    pub fn packaged(packager: &Packager<MemoryStorage>) -> Vec<(PathBuf, Vec<u8>)> {
+        packager.target.files.lock().unwrap().clone().into_iter().collect()
+    }
+}
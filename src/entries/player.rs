@@ -0,0 +1,106 @@
+use fastnbt::{IntArray, Value};
+
+use crate::formats::NbtFormat;
+use crate::PlayerFiles;
+use super::*;
+
+/// Player file from `playerdata/`, `stats/` or `advancements/` named after the player uuid.
+#[derive(Clone, Debug, Deref, From)]
+pub struct PlayerEntry(PathBuf);
+
+impl Packageable for PlayerEntry {}
+
+impl<S: Storage> Visitor<PlayerEntry> for Packager<S> {
+    fn visit(&self, entry: &PlayerEntry) -> Result<()> {
+        let settings = &self.config.players;
+        let uuid = entry.file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+        let is_nbt = entry.extension().is_some_and(|ext| ext == "dat" || ext == "dat_old");
+
+        let files = match entry.parent().and_then(Path::file_name) {
+            _ if settings.whitelist.contains(&uuid) => PlayerFiles::Keep,
+            Some(dir) if dir == "playerdata" => settings.playerdata,
+            Some(dir) if dir == "stats" => settings.stats,
+            _ => settings.advancements,
+        };
+
+        match files {
+            PlayerFiles::Remove => Ok(()),
+            PlayerFiles::Keep if is_nbt && self.config.nbt_patches_for(entry).next().is_some() => {
+                self.write_nbt(entry, entry, &Value::load(entry)?)
+            },
+            PlayerFiles::Keep => self.target.copy(entry, &entry.prefix(self.config.dirname.as_ref())),
+            PlayerFiles::Anonymize => {
+                let anonymous = settings.anonymize(&uuid);
+                let mut name = format_uuid(anonymous);
+                if let Some(ext) = entry.extension() {
+                    name = format!("{name}.{}", ext.to_string_lossy());
+                }
+                let to = entry.with_file_name(name);
+
+                if !is_nbt {
+                    return self.target.copy(entry, &to.prefix(self.config.dirname.as_ref()));
+                }
+
+                let mut nbt = Value::load(entry)?;
+                if let Value::Compound(nbt) = &mut nbt {
+                    if nbt.contains_key("UUID") {
+                        nbt.insert("UUID".to_owned(), Value::IntArray(IntArray::new(vec![
+                            (anonymous >> 96) as i32,
+                            (anonymous >> 64) as i32,
+                            (anonymous >> 32) as i32,
+                            anonymous as i32,
+                        ])));
+                    }
+                    if nbt.contains_key("UUIDMost") {
+                        nbt.insert("UUIDMost".to_owned(), Value::Long((anonymous >> 64) as i64));
+                        nbt.insert("UUIDLeast".to_owned(), Value::Long(anonymous as i64));
+                    }
+                }
+
+                self.write_nbt(entry, &to, &nbt)
+            },
+        }
+    }
+}
+
+fn format_uuid(uuid: u128) -> String {
+    let hex = format!("{uuid:032x}");
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+
+    use super::*;
+    use crate::entries::tests::{packaged, packager, temp_dir, write};
+    use crate::Config;
+
+    const UUID: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+
+    #[test]
+    fn anonymizes_player_files() {
+        let world = temp_dir("players");
+        let player = nbt!({"UUID": [I; 0x069a79f4, 0x44e94726, -0x5a41_0357, 0x0e38aaf5], "Score": 7});
+        write(&world, &format!("playerdata/{UUID}.dat"), &player.to_bytes().unwrap());
+        write(&world, &format!("stats/{UUID}.json"), b"{}");
+
+        let mut config = Config::default();
+        config.players.playerdata = PlayerFiles::Anonymize;
+        config.players.stats = PlayerFiles::Anonymize;
+        let anonymous = config.players.anonymize(UUID);
+        let packager = packager(config, &world);
+        for file in [format!("playerdata/{UUID}.dat"), format!("stats/{UUID}.json")] {
+            packager.visit(&PlayerEntry(world.join(file))).unwrap();
+        }
+
+        let name = format_uuid(anonymous);
+        let files = packaged(&packager);
+        let paths: Vec<_> = files.iter().map(|(path, _)| path.strip_prefix(&world).unwrap()).collect();
+        assert_eq!(paths, [Path::new(&format!("playerdata/{name}.dat")), Path::new(&format!("stats/{name}.json"))]);
+
+        let player = Value::from_bytes(&files[0].1).unwrap();
+        let uuid = [(anonymous >> 96) as i32, (anonymous >> 64) as i32, (anonymous >> 32) as i32, anonymous as i32];
+        assert_eq!(player, nbt!({"UUID": IntArray::new(uuid.to_vec()), "Score": 7}));
+    }
+}
@@ -1,5 +1,10 @@
-use crate::formats::Region;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::formats::{ChunkDecision, ChunkDecisions, ChunkSet, ForcedChunks, NbtFormat, ProcessedRegion, Region};
 use crate::utils::PathUtils;
+use crate::Config;
 use super::*;
 
 #[derive(Clone, Debug, Deref, From)]
@@ -18,22 +23,272 @@ impl<S: Storage> Visitor<RegionEntry> for Packager<S> {
 
         let to = entry.prefix(self.config.dirname.as_ref());
 
-        if self.config.clean_chunks {
-            let contents = Region::load(entry)
-                .with_context(|| "could not read region")?
-                .optimize_bytes(&self.config)
-                .with_context(|| "could not process region")?;
+        if self.config.process_regions() {
+            let (region, decisions) = self.process_region(entry)?;
+            let region = region.optimize(&decisions);
+
+            if self.config.audit_chunks {
+                self.progress.suspend(|| for chunk in &region.chunks {
+                    let action = if chunk.emptiness.is_empty() { "removed" } else { "kept" };
+                    println!("{action} chunk {} {} ({}) [{}]", chunk.x, chunk.z, chunk.emptiness, entry.display());
+                });
+            }
+
+            self.progress.suspend(|| for chunk in &region.corrupt {
+                let action = if chunk.kept { "kept" } else { "skipped" };
+                log::warn!("{action} corrupt chunk {} {} ({:#}) [{}]", chunk.x, chunk.z, chunk.error, entry.display());
+            });
+
+            if region.legacy_lz4 > 0 {
+                self.progress.suspend(|| log::warn!(
+                    "kept the compression of {} chunks older than 1.20.5 (lz4 is not supported) [{}]",
+                    region.legacy_lz4, entry.display(),
+                ));
+            }
 
-            if contents.len() <= 8192 {
+            if region.bytes.len() <= 8192 {
                 self.progress.suspend(|| {
                     log::info!("skipped empty region [{}]", entry.display())
                 });
                 return Ok(());
             }
 
-            return self.target.write(&to, &contents);
+            for (name, data) in &region.external {
+                self.target.write(&to.with_file_name(name), data)?;
+            }
+            return self.target.write(&to, &region.bytes);
         }
 
+        for file in Region::external_files(entry) {
+            self.target.copy(&file, &to.with_file_name(file.file_name().unwrap_or_default()))?;
+        }
         self.target.copy(entry, &to)
     }
 }
+
+/// Region kinds sharing the same chunks in a dimension.
+const REGION_DIRS: [&str; 3] = ["region", "entities", "poi"];
+
+/// Regions of the same name in a dimension, processed together as they share the same chunks.
+#[derive(Default)]
+pub(crate) struct SiblingRegions {
+    regions: Mutex<HashMap<PathBuf, Result<ProcessedRegion>>>,
+    decisions: Arc<ChunkDecisions>,
+}
+
+impl<S: Storage> Packager<S> {
+    /// Process the region with the chunk decisions shared by the region kinds of its dimension.
+    /// Every accepted kind is processed once by the first of them to be visited.
+    fn process_region(&self, region: &Path) -> Result<(ProcessedRegion, Arc<ChunkDecisions>)> {
+        let process = |forced: &ChunkSet| -> Result<ProcessedRegion> {
+            Region::load(region)
+                .with_context(|| "could not read region")?
+                .process(&self.config, forced)
+                .with_context(|| "could not process region")
+        };
+
+        if !self.config.clean_chunks {
+            return Ok((process(&ChunkSet::default())?, Arc::default()));
+        }
+
+        let name = region.file_name().unwrap_or_default();
+        let cell: Arc<OnceLock<_>> = self.sibling_regions.lock().unwrap()
+            .entry(dimension(region).join(name))
+            .or_default()
+            .clone();
+
+        let siblings = cell.get_or_init(|| self.process_siblings(region));
+        let processed = siblings.regions.lock().unwrap().remove(region);
+        match processed {
+            Some(processed) => Ok((processed?, siblings.decisions.clone())),
+            // Regions outside of the region kinds directories are decided alone
+            None => {
+                let processed = process(&self.forced_chunks(region))?;
+                let decisions = processed.decisions().map(|chunk| ((chunk.x, chunk.z), chunk.emptiness)).collect();
+                Ok((processed, Arc::new(decisions)))
+            },
+        }
+    }
+
+    /// Process the accepted region kinds sharing the name of the region and merge their decisions.
+    /// Every chunk is kept when one of them could not be processed (the error is reported when packaged).
+    fn process_siblings(&self, region: &Path) -> SiblingRegions {
+        let forced = self.forced_chunks(region);
+        let mut regions = HashMap::new();
+        let mut decisions = Some(ChunkDecisions::new());
+        let paths = sibling_regions(&self.config, region);
+        let shared = paths.len() > 1;
+        for path in paths {
+            let processed = Region::load(&path)
+                .with_context(|| "could not read region")
+                .and_then(|region| region.process(&self.config, &forced).with_context(|| "could not process region"));
+            match (&processed, &mut decisions) {
+                (Ok(processed), Some(decisions)) => merge_decisions(decisions, processed.decisions()),
+                (Err(_), Some(_)) => {
+                    if shared {
+                        self.progress.suspend(|| {
+                            log::warn!("kept every chunk of the regions sharing its chunks [{}]", path.display())
+                        });
+                    }
+                    decisions = None;
+                },
+                _ => {},
+            }
+            regions.insert(path, processed);
+        }
+
+        SiblingRegions {
+            regions: Mutex::new(regions),
+            decisions: Arc::new(decisions.unwrap_or_default()),
+        }
+    }
+
+    /// Get the forced chunks of the dimension containing the region, computed once per dimension.
+    fn forced_chunks(&self, region: &Path) -> Arc<ChunkSet> {
+        let mut cache = self.forced_chunks.lock().unwrap();
+        cache.entry(dimension(region).to_owned()).or_insert_with(|| {
+            Arc::new(read_forced_chunks(&self.config, dimension(region)).unwrap_or_else(|err| {
+                let path = dimension(region).join("data/chunks.dat");
+                self.progress.suspend(|| {
+                    log::warn!("could not read forced chunks ({err:#}) [{}]", path.display())
+                });
+                ChunkSet::default()
+            }))
+        }).clone()
+    }
+}
+
+/// Get the dimension directory containing the region (e.g. `./DIM-1` for `./DIM-1/region/r.0.0.mca`).
+pub(crate) fn dimension(region: &Path) -> &Path {
+    region.parent().and_then(Path::parent).unwrap_or(Path::new("."))
+}
+
+/// Get the accepted region kinds sharing the name of the region in its dimension (`region/`, `entities/`, `poi/`).
+fn sibling_regions(config: &Config, region: &Path) -> Vec<PathBuf> {
+    let dimension = dimension(region);
+    let name = region.file_name().unwrap_or_default();
+    REGION_DIRS.iter()
+        .map(|kind| dimension.join(kind).join(name))
+        .filter(|path| path.metadata().is_ok_and(|meta| meta.is_file() && meta.len() > 8192))
+        .filter(|path| config.is_accepted(path))
+        .collect()
+}
+
+/// Merge the decisions of a region kind, a chunk is kept in every kind as soon as one of them keeps it.
+fn merge_decisions(decisions: &mut ChunkDecisions, chunks: impl Iterator<Item = ChunkDecision>) {
+    for chunk in chunks {
+        match decisions.entry((chunk.x, chunk.z)) {
+            Entry::Occupied(entry) => entry.into_mut().merge(chunk.emptiness),
+            Entry::Vacant(entry) => {
+                entry.insert(chunk.emptiness);
+            },
+        }
+    }
+}
+
+/// Decide the emptiness of the chunks shared by the region kinds of a dimension without processing them.
+pub(crate) fn decide_chunks(config: &Config, region: &Path, forced: &ChunkSet) -> Result<ChunkDecisions> {
+    let mut decisions = ChunkDecisions::new();
+    for path in sibling_regions(config, region) {
+        let chunks = Region::load(&path)
+            .and_then(|region| region.decide(config, forced))
+            .with_context(|| format!("could not read {}", path.display()))?;
+        merge_decisions(&mut decisions, chunks.into_iter());
+    }
+
+    Ok(decisions)
+}
+
+/// Read the forced chunks of a dimension (from `data/chunks.dat`).
+/// Nothing is forced when forced chunks are removed from the package.
+pub(crate) fn read_forced_chunks(config: &Config, dimension: &Path) -> Result<ChunkSet> {
+    let path = dimension.join("data/chunks.dat");
+    if config.data.remove_forced_chunks || !path.is_file() {
+        return Ok(ChunkSet::default());
+    }
+
+    Ok(ForcedChunks::load(&path)?.positions().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+    use ignore::overrides::OverrideBuilder;
+
+    use super::*;
+    use crate::entries::tests::{temp_dir, write};
+    use crate::formats::{Criterion, Emptiness, RawChunk, Scheme};
+    use crate::storage::MemoryStorage;
+
+    fn region(chunks: &[fastnbt::Value]) -> Vec<u8> {
+        let chunks: Vec<_> = chunks.iter().enumerate().map(|(x, chunk)| {
+            RawChunk::compress(x, 0, 0, &fastnbt::to_bytes(chunk).unwrap(), Scheme::Zlib, 6).unwrap()
+        }).collect();
+        crate::formats::anvil::write_chunks(&chunks).0
+    }
+
+    fn packager(world: &Path) -> Packager<MemoryStorage> {
+        let mut config = Config::default();
+        config.clean_chunks = true;
+        let mut accepted = OverrideBuilder::new(world);
+        for pattern in ["region/*.mca", "entities/*.mca", "poi/*.mca", "custom/*.mca"] {
+            accepted.add(pattern).unwrap();
+        }
+        config.accepted_entries = accepted.build().unwrap();
+        crate::entries::tests::packager(config, world)
+    }
+
+    #[test]
+    fn merges_the_decisions_of_sibling_regions() {
+        let world = temp_dir("siblings");
+        let air = nbt!({
+            "DataVersion": 3465,
+            "Status": "minecraft:full",
+            "sections": [{"block_states": {"palette": [{"Name": "minecraft:air"}]}}],
+        });
+        let stone = nbt!({
+            "DataVersion": 3465,
+            "Status": "minecraft:full",
+            "sections": [{"block_states": {"palette": [{"Name": "minecraft:stone"}]}}],
+        });
+        let entities = nbt!({"DataVersion": 3465, "Entities": [{"id": "minecraft:pig"}]});
+        let no_entities = nbt!({"DataVersion": 3465, "Entities": []});
+        let no_poi = nbt!({"DataVersion": 3465, "Sections": {}});
+        write(&world, "region/r.0.0.mca", &region(&[air.clone(), air.clone(), stone]));
+        write(&world, "entities/r.0.0.mca", &region(&[entities, no_entities]));
+        write(&world, "poi/r.0.0.mca", &region(&[no_poi]));
+        write(&world, "custom/r.0.0.mca", &region(std::slice::from_ref(&air)));
+
+        // The first chunk of entities/r.1.0.mca points past the end of the file
+        let mut corrupt = vec![0; 12288];
+        corrupt[..4].copy_from_slice(&(100 << 8 | 1_u32).to_be_bytes());
+        write(&world, "region/r.1.0.mca", &region(std::slice::from_ref(&air)));
+        write(&world, "entities/r.1.0.mca", &corrupt);
+
+        let packager = packager(&world);
+        let (processed, decisions) = packager.process_region(&world.join("region/r.0.0.mca")).unwrap();
+        let expected = ChunkDecisions::from([
+            ((0, 0), Emptiness::Kept(vec![Criterion::Entities])),
+            ((1, 0), Emptiness::Empty),
+            ((2, 0), Emptiness::Kept(vec![Criterion::Blocks])),
+        ]);
+        assert_eq!(*decisions, expected);
+        let optimized = processed.optimize(&decisions);
+        let kept = optimized.chunks.iter().filter(|chunk| !chunk.emptiness.is_empty());
+        assert_eq!(kept.map(|chunk| chunk.x).collect::<Vec<_>>(), [0, 2]);
+
+        // Siblings are processed once and share the decisions
+        let (_, sibling) = packager.process_region(&world.join("entities/r.0.0.mca")).unwrap();
+        assert!(Arc::ptr_eq(&decisions, &sibling));
+
+        // Every chunk is kept when a sibling could not be processed
+        let (processed, decisions) = packager.process_region(&world.join("region/r.1.0.mca")).unwrap();
+        assert!(decisions.is_empty());
+        assert!(processed.optimize(&decisions).bytes.len() > 8192);
+        assert!(packager.process_region(&world.join("entities/r.1.0.mca")).is_err());
+
+        // Regions outside of the region kinds directories are decided alone
+        let (_, decisions) = packager.process_region(&world.join("custom/r.0.0.mca")).unwrap();
+        assert_eq!(*decisions, ChunkDecisions::from([((0, 0), Emptiness::Empty)]));
+    }
+}
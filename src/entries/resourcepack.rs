@@ -1,19 +1,137 @@
+use std::collections::btree_map::{BTreeMap, Entry as MapEntry};
+
+use serde_json::Value;
+
+use crate::storage::{self, InMemoryStorage, ZipStorage};
 use crate::utils::PathUtils;
 use crate::utils;
 use super::*;
 
-#[derive(Clone, Debug, Deref, From)]
-pub struct ResourcepackEntry(PathBuf);
+#[derive(Clone, Debug, From)]
+pub struct ResourcepackEntry(Vec<PathBuf>);
+
+const RESOURCES: &str = "resources.zip";
+
+impl ResourcepackEntry {
+    /// Get the path of the packaged resourcepack, every pack is merged into it.
+    pub fn path(&self) -> &Path {
+        Path::new(RESOURCES)
+    }
+
+    /// Merge the resourcepacks in order, later packs override earlier files.
+    fn merge(&self) -> Result<Vec<u8>> {
+        let mut files: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+        for pack in &self.0 {
+            let pack = storage::open(pack)
+                .with_context(|| format!("could not read resourcepack {}", pack.display()))?;
+            for file in pack.files()? {
+                let contents = pack.read(&file)?;
+                match files.entry(file) {
+                    MapEntry::Occupied(mut entry) if is_language_file(entry.key()) => {
+                        let merged = merge_json(entry.get(), &contents)
+                            .with_context(|| format!("could not merge {}", entry.key().display()))?;
+                        entry.insert(merged);
+                    },
+                    MapEntry::Occupied(mut entry) => { entry.insert(contents); },
+                    MapEntry::Vacant(entry) => { entry.insert(contents); },
+                }
+            }
+        }
+
+        let mut zip = ZipStorage::new(&[]);
+        for (file, contents) in files {
+            zip.write(&file, &contents)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+}
+
+/// Check if the file is a language file (`assets/<namespace>/lang/<language>.json`).
+fn is_language_file(path: &Path) -> bool {
+    let components: Vec<_> = path.components().map(|component| component.as_os_str()).collect();
+    matches!(components.as_slice(), [assets, _, lang, _] if *assets == "assets" && *lang == "lang")
+        && path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn merge_json(base: &[u8], contents: &[u8]) -> Result<Vec<u8>> {
+    let mut base: Value = serde_json::from_slice(base)?;
+    deep_merge(&mut base, serde_json::from_slice(contents)?);
+
+    Ok(serde_json::to_vec_pretty(&base)?)
+}
+
+fn deep_merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(base) => deep_merge(base, value),
+                    None => { base.insert(key, value); },
+                }
+            }
+        },
+        (base, value) => *base = value,
+    }
+}
 
 impl Packageable for ResourcepackEntry {}
 
 impl<S: Storage> Visitor<ResourcepackEntry> for Packager<S> {
     fn visit(&self, entry: &ResourcepackEntry) -> Result<()> {
-        let to = PathBuf::from("resources.zip").prefix(self.config.dirname.as_ref());
+        let to = PathBuf::from(RESOURCES).prefix(self.config.dirname.as_ref());
 
-        match entry.is_file() {
-            true => self.target.copy(entry, &to),
-            false => self.target.write(&to, &utils::create_zip_from_dir(entry)?),
+        match entry.0.as_slice() {
+            [pack] if pack.is_file() => self.target.copy(pack, &to),
+            [pack] => self.target.write(&to, &utils::create_zip_from_dir(pack)?),
+            _ => self.target.write(&to, &entry.merge()?),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::entries::tests::{temp_dir, write};
+
+    #[test]
+    fn merges_json_deeply() {
+        let mut base = json!({"a": {"b": 1, "c": [1, 2]}, "d": "base"});
+        deep_merge(&mut base, json!({"a": {"c": [3], "e": true}, "f": null}));
+        assert_eq!(base, json!({"a": {"b": 1, "c": [3], "e": true}, "d": "base", "f": null}));
+    }
+
+    #[test]
+    fn merges_resourcepacks() {
+        let dir = temp_dir("resourcepacks");
+        let (base, extra) = (dir.join("base"), dir.join("extra"));
+        write(&base, "pack.mcmeta", br#"{"pack": {"pack_format": 15}}"#);
+        write(&base, "assets/minecraft/lang/en_us.json", br#"{"item.a": "A", "item.b": "B"}"#);
+        write(&base, "assets/minecraft/textures/block/stone.png", b"base");
+        write(&extra, "pack.mcmeta", br#"{"pack": {"pack_format": 34}}"#);
+        write(&extra, "assets/minecraft/lang/en_us.json", br#"{"item.b": "Extra B", "item.c": "C"}"#);
+        write(&extra, "assets/minecraft/textures/block/stone.png", b"extra");
+
+        let merged = ResourcepackEntry(vec![base, extra]).merge().unwrap();
+        std::fs::write(dir.join(RESOURCES), merged).unwrap();
+        let zip = storage::open(&dir.join(RESOURCES)).unwrap();
+        let read = |file: &str| zip.read(Path::new(file)).unwrap();
+
+        let lang: Value = serde_json::from_slice(&read("assets/minecraft/lang/en_us.json")).unwrap();
+        assert_eq!(lang, json!({"item.a": "A", "item.b": "Extra B", "item.c": "C"}));
+        // Other files are overridden by the later packs (mcmeta included)
+        assert_eq!(read("assets/minecraft/textures/block/stone.png"), b"extra");
+        let mcmeta: Value = serde_json::from_slice(&read("pack.mcmeta")).unwrap();
+        assert_eq!(mcmeta, json!({"pack": {"pack_format": 34}}));
+    }
+
+    #[test]
+    fn detects_language_files() {
+        assert!(is_language_file(Path::new("assets/minecraft/lang/en_us.json")));
+        assert!(!is_language_file(Path::new("assets/minecraft/lang/en_us.lang")));
+        assert!(!is_language_file(Path::new("assets/minecraft/models/lang/stone.json")));
+        assert!(!is_language_file(Path::new("data/lang/en_us.json")));
+    }
+}
@@ -1,5 +1,4 @@
 use crate::formats::{NbtFormat, Scoreboard};
-use crate::utils::PathUtils;
 use super::*;
 
 #[derive(Clone, Debug, Deref, From)]
@@ -21,7 +20,84 @@ impl<S: Storage> Visitor<ScoreboardEntry> for Packager<S> {
                 self.config.accepted_objectives.is_match(&e.name)
             });
         }
+        if !self.config.accepted_objective_scores.is_empty() {
+            nbt.data.scores.retain(|e| {
+                self.config.accepted_objective_scores.iter()
+                    .filter(|(objective, _)| objective.is_match(&e.objective))
+                    .all(|(_, scores)| scores.is_match(&e.name))
+            });
+        }
+        if !self.config.accepted_teams.is_empty() {
+            nbt.data.teams.retain(|e| {
+                self.config.accepted_teams.is_match(&e.name)
+            });
+        }
+        for (objective, scores) in &self.config.set_scores {
+            if !nbt.has_objective(objective) {
+                self.progress.suspend(|| {
+                    log::warn!("could not set scores of missing objective {objective} [{}]", entry.display())
+                });
+                continue;
+            }
+            for (name, value) in scores {
+                match value {
+                    Some(value) => nbt.set_score(name, objective, *value),
+                    None => nbt.reset_score(name, objective),
+                }
+            }
+        }
+        nbt.remove_orphans();
+
+        self.write_nbt(entry, entry, &nbt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::{nbt, Value};
+    use globset::{Glob, GlobSet, GlobSetBuilder};
+
+    use super::*;
+    use crate::entries::tests::{packaged, packager, temp_dir, write};
+    use crate::Config;
+
+    fn globset(pattern: &str) -> GlobSet {
+        GlobSetBuilder::new().add(Glob::new(pattern).unwrap()).build().unwrap()
+    }
+
+    #[test]
+    fn removes_the_scores_of_removed_objectives() {
+        let world = temp_dir("scoreboard");
+        let scoreboard = nbt!({
+            "DataVersion": 3465,
+            "data": {
+                "PlayerScores": [
+                    {"Name": "alex", "Objective": "kills", "Score": 3, "Locked": 0_i8},
+                    {"Name": "alex", "Objective": "debug", "Score": 1, "Locked": 0_i8},
+                    {"Name": "#timer", "Objective": "debug", "Score": 20, "Locked": 0_i8},
+                ],
+                "Objectives": [{"Name": "kills"}, {"Name": "debug"}],
+                "Teams": [{"Name": "red", "Players": ["alex"]}, {"Name": "debug_team", "Players": ["#timer"]}],
+                "DisplaySlots": {"sidebar": "debug", "list": "kills"},
+            },
+        });
+        write(&world, "data/scoreboard.dat", &scoreboard.to_bytes().unwrap());
+
+        let mut config = Config::default();
+        config.accepted_objectives = globset("kills");
+        config.accepted_teams = globset("red");
+        let packager = packager(config, &world);
+        packager.visit(&ScoreboardEntry(world.join("data/scoreboard.dat"))).unwrap();
 
-        self.target.write(&entry.prefix(self.config.dirname.as_ref()), &nbt.to_bytes()?)
+        let scoreboard = Value::from_bytes(&packaged(&packager)[0].1).unwrap();
+        assert_eq!(scoreboard, nbt!({
+            "DataVersion": 3465_i16,
+            "data": {
+                "PlayerScores": [{"Name": "alex", "Objective": "kills", "Score": 3, "Locked": 0_i8}],
+                "Objectives": [{"Name": "kills"}],
+                "Teams": [{"Name": "red", "Players": ["alex"]}],
+                "DisplaySlots": {"list": "kills"},
+            },
+        }));
     }
 }
@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use crate::formats::{CommandStorage, NbtFormat};
+use super::*;
+
+#[derive(Clone, Debug, Deref, From)]
+pub struct StorageEntry(PathBuf);
+
+impl Packageable for StorageEntry {}
+
+impl StorageEntry {
+    /// Get the namespace from the file name (`command_storage_<namespace>.dat`).
+    fn namespace(&self) -> String {
+        let name = self.file_stem().unwrap_or_default().to_string_lossy();
+        name.trim_start_matches("command_storage_").to_owned()
+    }
+}
+
+impl<S: Storage> Visitor<StorageEntry> for Packager<S> {
+    fn visit(&self, entry: &StorageEntry) -> Result<()> {
+        let mut nbt = CommandStorage::load(entry)?;
+        let namespace = entry.namespace();
+        let id = |key: &str| format!("{namespace}:{key}");
+
+        if !self.config.accepted_storages.is_empty() {
+            nbt.data.contents.retain(|key, _| {
+                self.config.accepted_storages.is_match(id(key))
+            });
+        }
+        if !self.config.rejected_storages.is_empty() {
+            nbt.data.contents.retain(|key, _| {
+                !self.config.rejected_storages.is_match(id(key))
+            });
+        }
+        for (storage, value) in &self.config.set_storages {
+            if let Some(key) = storage.strip_prefix(&format!("{namespace}:")) {
+                nbt.data.contents.insert(key.to_owned(), value.to_owned());
+            }
+        }
+
+        self.write_nbt(entry, entry, &nbt)
+    }
+}
+
+impl<S: Storage> Packager<S> {
+    /// Warn about the storages set in namespaces without command storage file (no file is created).
+    pub(crate) fn check_set_storages(&self, entries: &[Entry]) {
+        let namespaces: HashSet<_> = entries.iter().filter_map(|entry| match entry {
+            Entry::Storage(entry) => Some(entry.namespace()),
+            _ => None,
+        }).collect();
+        for (storage, _) in &self.config.set_storages {
+            let namespace = storage.split_once(':').map_or("", |(namespace, _)| namespace);
+            if !namespaces.contains(namespace) {
+                let path = format!("./data/command_storage_{namespace}.dat");
+                self.progress.suspend(|| log::warn!("could not set storage {storage} of missing namespace [{path}]"));
+            }
+        }
+    }
+}
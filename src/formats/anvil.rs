@@ -0,0 +1,372 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, bail, Error, Result};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::Deserialize;
+
+const SECTOR: usize = 4096;
+const HEADER: usize = 2 * SECTOR;
+const MAX_SECTORS: usize = 255;
+const EXTERNAL: u8 = 0x80;
+
+// Framing of lz4-java `LZ4BlockOutputStream` (used by minecraft since 1.20.5)
+const LZ4_MAGIC: &[u8] = b"LZ4Block";
+const LZ4_HEADER: usize = LZ4_MAGIC.len() + 13;
+const LZ4_BLOCK_SIZE: usize = 1 << 16;
+const LZ4_LEVEL: u8 = 6;
+const LZ4_RAW: u8 = 0x10;
+const LZ4_COMPRESSED: u8 = 0x20;
+const LZ4_SEED: u32 = 0x9747b28c;
+
+/// Compression scheme of a chunk in region files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Gzip = 1,
+    Zlib = 2,
+    #[serde(rename = "none")]
+    Uncompressed = 3,
+    Lz4 = 4,
+}
+
+impl Scheme {
+    fn from_id(id: u8) -> Result<Self> {
+        Ok(match id {
+            1 => Self::Gzip,
+            2 => Self::Zlib,
+            3 => Self::Uncompressed,
+            4 => Self::Lz4,
+            id => bail!("unsupported chunk compression scheme ({id})"),
+        })
+    }
+}
+
+/// Chunk as stored in a region file (with its compressed payload).
+pub struct RawChunk {
+    pub x: usize,
+    pub z: usize,
+    pub timestamp: u32,
+    pub scheme: Scheme,
+    pub payload: Vec<u8>,
+}
+
+impl RawChunk {
+    /// Compress the chunk nbt with the given scheme (the level is only used by gzip and zlib).
+    pub fn compress(x: usize, z: usize, timestamp: u32, data: &[u8], scheme: Scheme, level: u32) -> Result<Self> {
+        let level = flate2::Compression::new(level);
+        let payload = match scheme {
+            Scheme::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], level);
+                encoder.write_all(data)?;
+                encoder.finish()?
+            },
+            Scheme::Zlib => {
+                let mut encoder = ZlibEncoder::new(vec![], level);
+                encoder.write_all(data)?;
+                encoder.finish()?
+            },
+            Scheme::Uncompressed => data.to_vec(),
+            Scheme::Lz4 => lz4_compress(data),
+        };
+
+        Ok(Self { x, z, timestamp, scheme, payload })
+    }
+
+    pub fn decompress(&self) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        match self.scheme {
+            Scheme::Gzip => { GzDecoder::new(&self.payload[..]).read_to_end(&mut data)?; },
+            Scheme::Zlib => { ZlibDecoder::new(&self.payload[..]).read_to_end(&mut data)?; },
+            Scheme::Uncompressed => data.extend_from_slice(&self.payload),
+            Scheme::Lz4 => data = lz4_decompress(&self.payload)?,
+        }
+
+        Ok(data)
+    }
+}
+
+/// Chunk that could not be read from a region file (in region coordinates).
+#[derive(Debug)]
+pub struct CorruptChunk {
+    pub x: usize,
+    pub z: usize,
+    pub error: Error,
+}
+
+/// Iterate over the chunks of a region file (in header order).
+/// Payloads of chunks stored in external `.mcc` files are loaded with the given function.
+pub fn read_chunks<'a, F>(bytes: &'a [u8], external: F) -> Result<impl Iterator<Item = Result<RawChunk, CorruptChunk>> + 'a>
+where
+    F: Fn(usize, usize) -> Result<Vec<u8>> + 'a,
+{
+    if bytes.len() < HEADER {
+        bail!("region header is truncated");
+    }
+
+    Ok((0..1024).filter_map(move |index| {
+        let location = read_u32(bytes, index * 4);
+        let timestamp = read_u32(bytes, SECTOR + index * 4);
+        match location {
+            0 => None,
+            location => Some(read_chunk(bytes, index, location, timestamp, &external).map_err(|error| {
+                CorruptChunk { x: index % 32, z: index / 32, error }
+            })),
+        }
+    }))
+}
+
+/// Write a compact region file with the given chunks.
+/// Chunks too large for the region file are returned to be written in external `.mcc` files.
+pub fn write_chunks(chunks: &[RawChunk]) -> (Vec<u8>, Vec<&RawChunk>) {
+    let mut bytes = vec![0; HEADER];
+    let mut external = vec![];
+    for chunk in chunks {
+        let index = (chunk.x % 32) + (chunk.z % 32) * 32;
+        let offset = bytes.len() / SECTOR;
+        let (length, sectors) = match (chunk.payload.len() + 5).div_ceil(SECTOR) {
+            sectors if sectors > MAX_SECTORS => {
+                external.push(chunk);
+                (1, 1)
+            },
+            sectors => (chunk.payload.len() + 1, sectors),
+        };
+
+        bytes[index * 4..index * 4 + 4].copy_from_slice(&((offset << 8 | sectors) as u32).to_be_bytes());
+        bytes[SECTOR + index * 4..SECTOR + index * 4 + 4].copy_from_slice(&chunk.timestamp.to_be_bytes());
+        bytes.extend_from_slice(&(length as u32).to_be_bytes());
+        match length {
+            1 => bytes.push(chunk.scheme as u8 | EXTERNAL),
+            _ => {
+                bytes.push(chunk.scheme as u8);
+                bytes.extend_from_slice(&chunk.payload);
+            },
+        }
+        bytes.resize((offset + sectors) * SECTOR, 0);
+    }
+
+    (bytes, external)
+}
+
+/// Check if any chunk of the region file is stored in an external `.mcc` file.
+pub fn has_external_chunks(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER && (0..1024).any(|index| {
+        let start = (read_u32(bytes, index * 4) >> 8) as usize * SECTOR;
+        start >= HEADER && bytes.get(start + 4).is_some_and(|scheme| scheme & EXTERNAL != 0)
+    })
+}
+
+fn read_chunk<F>(bytes: &[u8], index: usize, location: u32, timestamp: u32, external: F) -> Result<RawChunk>
+where
+    F: Fn(usize, usize) -> Result<Vec<u8>>,
+{
+    let (x, z) = (index % 32, index / 32);
+    let start = (location >> 8) as usize * SECTOR;
+    let length = match bytes.get(start..start + 5) {
+        Some(_) => read_u32(bytes, start) as usize,
+        None => bail!("out of the region file"),
+    };
+    let payload = match length {
+        0 => bail!("no data"),
+        _ => bytes.get(start + 5..start + 4 + length).ok_or_else(|| anyhow!("truncated data"))?,
+    };
+
+    let scheme = bytes[start + 4];
+    Ok(RawChunk {
+        x,
+        z,
+        timestamp,
+        scheme: Scheme::from_id(scheme & !EXTERNAL)?,
+        payload: match scheme & EXTERNAL {
+            0 => payload.to_vec(),
+            _ => external(x, z)?,
+        },
+    })
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn lz4_checksum(data: &[u8]) -> u32 {
+    xxhash_rust::xxh32::xxh32(data, LZ4_SEED) & 0x0FFF_FFFF
+}
+
+fn lz4_compress(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    let mut write_block = |method: u8, block: &[u8], length: usize, checksum: u32| {
+        output.extend_from_slice(LZ4_MAGIC);
+        output.push(method | LZ4_LEVEL);
+        output.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        output.extend_from_slice(&(length as u32).to_le_bytes());
+        output.extend_from_slice(&checksum.to_le_bytes());
+        output.extend_from_slice(block);
+    };
+
+    for block in data.chunks(LZ4_BLOCK_SIZE) {
+        let compressed = lz4_flex::block::compress(block);
+        match compressed.len() < block.len() {
+            true => write_block(LZ4_COMPRESSED, &compressed, block.len(), lz4_checksum(block)),
+            false => write_block(LZ4_RAW, block, block.len(), lz4_checksum(block)),
+        }
+    }
+    write_block(LZ4_RAW, &[], 0, 0);
+
+    output
+}
+
+fn lz4_decompress(mut input: &[u8]) -> Result<Vec<u8>> {
+    let mut output = vec![];
+    while !input.is_empty() {
+        if input.len() < LZ4_HEADER || !input.starts_with(LZ4_MAGIC) {
+            bail!("invalid lz4 block header");
+        }
+
+        let read_le = |at: usize| u32::from_le_bytes([input[at], input[at + 1], input[at + 2], input[at + 3]]);
+        let method = input[8] & 0xF0;
+        let (compressed, length, checksum) = (read_le(9) as usize, read_le(13) as usize, read_le(17));
+        if length == 0 {
+            break;
+        }
+
+        let block = input.get(LZ4_HEADER..LZ4_HEADER + compressed).ok_or_else(|| anyhow!("truncated lz4 block"))?;
+        let data = match method {
+            LZ4_RAW => block.to_vec(),
+            LZ4_COMPRESSED => lz4_flex::block::decompress(block, length)?,
+            method => bail!("unsupported lz4 block method ({method})"),
+        };
+        if lz4_checksum(&data) != checksum {
+            bail!("invalid lz4 block checksum");
+        }
+
+        output.extend_from_slice(&data);
+        input = &input[LZ4_HEADER + compressed..];
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stream framed like lz4-java `LZ4BlockOutputStream`: a compressed block of `minecraft:` repeated
+    /// 8 times (10 literals then a 65 bytes match) followed by the empty block written on close.
+    const LZ4_JAVA_STREAM: &[u8] = &[
+        b'L', b'Z', b'4', b'B', b'l', b'o', b'c', b'k', 0x26, 20, 0, 0, 0, 80, 0, 0, 0, 0x7d, 0x4e, 0xb0, 0x0f,
+        0xaf, b'm', b'i', b'n', b'e', b'c', b'r', b'a', b'f', b't', b':', 10, 0, 46, 0x50, b'r', b'a', b'f', b't', b':',
+        b'L', b'Z', b'4', b'B', b'l', b'o', b'c', b'k', 0x16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    /// Generate bytes that are either compressible or not.
+    fn bytes(len: usize, compressible: bool) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len).map(|i| match compressible {
+            true => (i / 7 % 32) as u8,
+            false => {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            },
+        }).collect()
+    }
+
+    fn chunk(x: usize, z: usize, payload: Vec<u8>) -> RawChunk {
+        RawChunk { x, z, timestamp: (x + z) as u32, scheme: Scheme::Zlib, payload }
+    }
+
+    #[test]
+    fn compresses_and_decompresses_every_scheme() {
+        let data = bytes(10_000, true);
+        for scheme in [Scheme::Gzip, Scheme::Zlib, Scheme::Uncompressed, Scheme::Lz4] {
+            let chunk = RawChunk::compress(1, 2, 3, &data, scheme, 6).unwrap();
+            assert_eq!(chunk.scheme, scheme);
+            assert_eq!(chunk.decompress().unwrap(), data, "{scheme:?}");
+        }
+    }
+
+    #[test]
+    fn decompresses_lz4_java_streams() {
+        let chunk = RawChunk { x: 0, z: 0, timestamp: 0, scheme: Scheme::Lz4, payload: LZ4_JAVA_STREAM.to_vec() };
+        assert_eq!(chunk.decompress().unwrap(), b"minecraft:".repeat(8));
+
+        let payload = lz4_compress(&b"minecraft:".repeat(8));
+        assert!(payload.starts_with(b"LZ4Block\x26"));
+        assert!(payload.ends_with(&LZ4_JAVA_STREAM[LZ4_JAVA_STREAM.len() - LZ4_HEADER..]));
+    }
+
+    #[test]
+    fn splits_lz4_input_in_blocks() {
+        for compressible in [true, false] {
+            let data = bytes(2 * LZ4_BLOCK_SIZE + 100, compressible);
+            let payload = lz4_compress(&data);
+            let blocks = payload.windows(LZ4_MAGIC.len()).filter(|window| *window == LZ4_MAGIC).count();
+            assert_eq!(blocks, 4);
+            assert_eq!(lz4_decompress(&payload).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_lz4_checksums() {
+        let mut payload = LZ4_JAVA_STREAM.to_vec();
+        payload[17] ^= 1;
+        assert!(lz4_decompress(&payload).is_err());
+    }
+
+    #[test]
+    fn writes_empty_regions() {
+        let (bytes, external) = write_chunks(&[]);
+        assert_eq!(bytes, vec![0; 8192]);
+        assert!(external.is_empty());
+        assert_eq!(read_chunks(&bytes, |_, _| unreachable!()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn pads_chunks_to_sectors() {
+        let chunks = [chunk(3, 0, bytes(5000, false)), chunk(0, 1, bytes(100, false))];
+        let (bytes, _) = write_chunks(&chunks);
+        assert_eq!(bytes.len(), HEADER + 3 * SECTOR);
+        assert_eq!(read_u32(&bytes, 3 * 4), 2 << 8 | 2);
+        assert_eq!(read_u32(&bytes, 32 * 4), 4 << 8 | 1);
+        assert_eq!(read_u32(&bytes, SECTOR + 3 * 4), 3);
+        assert_eq!(read_u32(&bytes, 2 * SECTOR), 5001);
+        assert_eq!(bytes[2 * SECTOR + 4], Scheme::Zlib as u8);
+        assert_eq!(read_u32(&bytes, 4 * SECTOR), 101);
+
+        let read: Vec<_> = read_chunks(&bytes, |_, _| unreachable!()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(read.len(), 2);
+        for (read, chunk) in read.iter().zip(&chunks) {
+            assert_eq!((read.x, read.z, read.timestamp), (chunk.x, chunk.z, chunk.timestamp));
+            assert_eq!(read.scheme, chunk.scheme);
+            assert_eq!(read.payload, chunk.payload);
+        }
+    }
+
+    #[test]
+    fn writes_oversized_chunks_externally() {
+        let chunks = [chunk(1, 1, bytes(MAX_SECTORS * SECTOR, false))];
+        let (bytes, external) = write_chunks(&chunks);
+        assert_eq!(bytes.len(), HEADER + SECTOR);
+        assert_eq!(external.len(), 1);
+        assert_eq!(read_u32(&bytes, HEADER), 1);
+        assert_eq!(bytes[HEADER + 4], Scheme::Zlib as u8 | EXTERNAL);
+
+        let mut read = read_chunks(&bytes, |x, z| {
+            assert_eq!((x, z), (1, 1));
+            Ok(chunks[0].payload.to_owned())
+        }).unwrap();
+        assert_eq!(read.next().unwrap().unwrap().payload, chunks[0].payload);
+        assert!(has_external_chunks(&bytes));
+        assert!(!has_external_chunks(&write_chunks(&[chunk(1, 1, vec![1])]).0));
+    }
+
+    #[test]
+    fn reports_corrupt_chunks() {
+        let (mut bytes, _) = write_chunks(&[chunk(2, 0, vec![1, 2, 3])]);
+        bytes.truncate(HEADER + 6);
+        let Some(Err(corrupt)) = read_chunks(&bytes, |_, _| unreachable!()).unwrap().next() else {
+            panic!("the chunk is corrupt");
+        };
+        assert_eq!((corrupt.x, corrupt.z), (2, 0));
+        assert!(read_chunks(&bytes[..100], |_, _| unreachable!()).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+use fastnbt::LongArray;
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+const SECTION_VOLUME: usize = 4096;
+
+/// A block state as written in palettes (e.g. `minecraft:water[level=0]`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockState {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Properties", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+}
+
+impl BlockState {
+    /// Check the name and the properties given by this state, other properties are ignored.
+    pub fn matches(&self, state: &BlockState) -> bool {
+        self.name == state.name
+            && self.properties.iter().all(|(key, value)| state.properties.get(key) == Some(value))
+    }
+}
+
+impl FromStr for BlockState {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (name, properties) = match value.split_once('[') {
+            Some((name, properties)) => match properties.strip_suffix(']') {
+                Some(properties) => (name, properties),
+                None => bail!("missing closing bracket in block state '{value}'"),
+            },
+            None => (value, ""),
+        };
+
+        let properties = properties.split(',')
+            .filter(|property| !property.trim().is_empty())
+            .map(|property| match property.split_once('=') {
+                Some((key, value)) => Ok((key.trim().to_owned(), value.trim().to_owned())),
+                None => bail!("invalid property '{property}' in block state '{value}'"),
+            })
+            .collect::<Result<_>>()?;
+
+        let name = name.trim();
+        Ok(Self {
+            name: match name.contains(':') {
+                true => name.to_owned(),
+                false => format!("minecraft:{name}"),
+            },
+            properties,
+        })
+    }
+}
+
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.properties.is_empty() {
+            let properties: Vec<_> = self.properties.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            write!(f, "[{}]", properties.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Layout of the packed block indices in sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packing {
+    /// 1.13 to 1.15, indices may span across two longs.
+    Spanning,
+    /// 1.16 and 1.17, indices never span across two longs.
+    Padded,
+    /// 1.18+, same as padded but omitted for single entry palettes.
+    Modern,
+}
+
+impl Packing {
+    /// Get the packing of legacy chunks (nested in `Level`), top-level sections are always modern.
+    pub fn from_legacy_version(data_version: i32) -> Self {
+        match data_version {
+            ..=2528 => Self::Spanning,
+            2529..=2843 => Self::Padded,
+            _ => Self::Modern,
+        }
+    }
+
+    fn unpack(self, data: &[i64], len: usize) -> Vec<usize> {
+        if data.is_empty() {
+            return vec![0; SECTION_VOLUME];
+        }
+
+        let bits = bits(len);
+        let mask = (1u64 << bits) - 1;
+        let long = |i: usize| data.get(i).copied().unwrap_or_default() as u64;
+        (0..SECTION_VOLUME).map(|i| match self {
+            Self::Spanning => {
+                let (index, offset) = (i * bits / 64, i * bits % 64);
+                let mut value = long(index) >> offset;
+                if offset + bits > 64 {
+                    value |= long(index + 1) << (64 - offset);
+                }
+                (value & mask) as usize
+            },
+            _ => {
+                let per_long = 64 / bits;
+                ((long(i / per_long) >> ((i % per_long) * bits)) & mask) as usize
+            },
+        }).collect()
+    }
+
+    fn pack(self, indices: &[usize], len: usize) -> Option<LongArray> {
+        if self == Self::Modern && len <= 1 {
+            return None;
+        }
+
+        let bits = bits(len);
+        let data = match self {
+            Self::Spanning => {
+                let mut data = vec![0u64; (SECTION_VOLUME * bits).div_ceil(64)];
+                for (i, &index) in indices.iter().enumerate() {
+                    let (long, offset) = (i * bits / 64, i * bits % 64);
+                    data[long] |= (index as u64) << offset;
+                    if offset + bits > 64 {
+                        data[long + 1] |= (index as u64) >> (64 - offset);
+                    }
+                }
+                data
+            },
+            _ => {
+                let per_long = 64 / bits;
+                let mut data = vec![0u64; SECTION_VOLUME.div_ceil(per_long)];
+                for (i, &index) in indices.iter().enumerate() {
+                    data[i / per_long] |= (index as u64) << ((i % per_long) * bits);
+                }
+                data
+            },
+        };
+
+        Some(LongArray::new(data.into_iter().map(|long| long as i64).collect()))
+    }
+}
+
+/// Remove duplicated palette entries and repack the block indices accordingly.
+pub fn dedupe_palette<T: PartialEq>(palette: &mut Vec<T>, data: &mut Option<LongArray>, packing: Packing) {
+    let mut mapping = Vec::with_capacity(palette.len());
+    let mut deduped: Vec<T> = Vec::with_capacity(palette.len());
+    let len = palette.len();
+    for item in palette.drain(..) {
+        match deduped.iter().position(|other| other == &item) {
+            Some(index) => mapping.push(index),
+            None => {
+                mapping.push(deduped.len());
+                deduped.push(item);
+            }
+        }
+    }
+
+    if deduped.len() != len {
+        let indices = packing.unpack(data.as_deref().unwrap_or_default(), len);
+        let indices: Vec<_> = indices.iter().map(|&i| mapping.get(i).copied().unwrap_or_default()).collect();
+        *data = packing.pack(&indices, deduped.len());
+    }
+    *palette = deduped;
+}
+
+fn bits(len: usize) -> usize {
+    (usize::BITS - len.saturating_sub(1).leading_zeros()).max(4) as usize
+}
+
+/// Block state patterns compiled once (e.g. `minecraft:*_air`, `#group`, `minecraft:water[level=0]`).
+/// Groups are resolved from the given tags and may reference other groups.
+#[derive(Debug, Default)]
+pub struct BlockMatcher {
+    names: GlobSet,
+    states: Vec<(GlobMatcher, BTreeMap<String, String>)>,
+}
+
+impl BlockMatcher {
+    pub fn new(patterns: &[String], tags: &HashMap<String, Vec<String>>) -> Result<Self> {
+        let mut expanded = vec![];
+        for pattern in patterns {
+            expand(pattern, tags, &mut vec![], &mut expanded)?;
+        }
+
+        let mut names = GlobSetBuilder::new();
+        let mut states = vec![];
+        for pattern in expanded {
+            let state: BlockState = pattern.parse()?;
+            let glob = Glob::new(&state.name)?;
+            match state.properties.is_empty() {
+                true => { names.add(glob); },
+                false => states.push((glob.compile_matcher(), state.properties)),
+            }
+        }
+
+        Ok(Self { names: names.build()?, states })
+    }
+
+    pub fn is_match(&self, state: &BlockState) -> bool {
+        self.names.is_match(&state.name) || self.states.iter().any(|(name, properties)| {
+            name.is_match(&state.name)
+                && properties.iter().all(|(key, value)| state.properties.get(key) == Some(value))
+        })
+    }
+}
+
+fn expand<'a>(
+    pattern: &'a str,
+    tags: &'a HashMap<String, Vec<String>>,
+    seen: &mut Vec<&'a str>,
+    expanded: &mut Vec<String>,
+) -> Result<()> {
+    let Some(tag) = pattern.strip_prefix('#') else {
+        expanded.push(pattern.to_owned());
+        return Ok(());
+    };
+    if seen.contains(&tag) {
+        bail!("block tag '#{tag}' references itself");
+    }
+
+    let patterns = tags.get(tag).ok_or_else(|| anyhow!("unknown block tag '#{tag}'"))?;
+    seen.push(tag);
+    for pattern in patterns {
+        expand(pattern, tags, seen, expanded)?;
+    }
+    seen.pop();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUTS: [Packing; 3] = [Packing::Spanning, Packing::Padded, Packing::Modern];
+
+    fn indices(len: usize) -> Vec<usize> {
+        (0..SECTION_VOLUME).map(|i| (i * 7 + i / 13) % len).collect()
+    }
+
+    #[test]
+    fn packs_and_unpacks_indices() {
+        // 4, 5 and 6 bits per entry
+        for len in [16, 17, 33, 64] {
+            for packing in LAYOUTS {
+                let indices = indices(len);
+                let data = packing.pack(&indices, len).unwrap();
+                assert_eq!(packing.unpack(&data, len), indices, "{packing:?} with {len} entries");
+            }
+        }
+    }
+
+    #[test]
+    fn packs_with_the_layout_length() {
+        let longs = |packing: Packing, len| packing.pack(&indices(len), len).unwrap().len();
+        assert_eq!(longs(Packing::Spanning, 16), 256);
+        assert_eq!(longs(Packing::Spanning, 17), 320);
+        assert_eq!(longs(Packing::Spanning, 33), 384);
+        assert_eq!(longs(Packing::Padded, 17), 342);
+        assert_eq!(longs(Packing::Modern, 33), 410);
+    }
+
+    #[test]
+    fn spans_indices_across_longs() {
+        // The 13th index (5 bits) starts at bit 60 of the first long
+        let mut indices = vec![0; SECTION_VOLUME];
+        indices[12] = 0b10111;
+        let data = Packing::Spanning.pack(&indices, 17).unwrap();
+        assert_eq!((data[0] as u64) >> 60, 0b0111);
+        assert_eq!(data[1] & 1, 1);
+
+        let data = Packing::Padded.pack(&indices, 17).unwrap();
+        assert_eq!(data[1], 0b10111);
+    }
+
+    #[test]
+    fn handles_single_entry_palettes() {
+        let zeros = vec![0; SECTION_VOLUME];
+        assert!(Packing::Modern.pack(&zeros, 1).is_none());
+        assert_eq!(Packing::Modern.unpack(&[], 1), zeros);
+        for packing in [Packing::Spanning, Packing::Padded] {
+            let data = packing.pack(&zeros, 1).unwrap();
+            assert_eq!(data.len(), 256);
+            assert_eq!(packing.unpack(&data, 1), zeros);
+        }
+    }
+
+    #[test]
+    fn dedupes_palettes() {
+        for packing in LAYOUTS {
+            let mut palette = (0..17).map(|i| i % 15).collect::<Vec<_>>();
+            let original = indices(17);
+            let mut data = packing.pack(&original, 17);
+            dedupe_palette(&mut palette, &mut data, packing);
+
+            assert_eq!(palette, (0..15).collect::<Vec<_>>());
+            let expected: Vec<_> = original.iter().map(|i| i % 15).collect();
+            assert_eq!(packing.unpack(data.as_deref().unwrap(), palette.len()), expected, "{packing:?}");
+        }
+
+        let mut palette = vec!["air", "air"];
+        let mut data = Packing::Modern.pack(&indices(2), 2);
+        dedupe_palette(&mut palette, &mut data, Packing::Modern);
+        assert_eq!(palette, ["air"]);
+        assert!(data.is_none());
+    }
+
+    #[test]
+    fn picks_legacy_packing_from_version() {
+        assert_eq!(Packing::from_legacy_version(1976), Packing::Spanning);
+        assert_eq!(Packing::from_legacy_version(2586), Packing::Padded);
+        assert_eq!(Packing::from_legacy_version(2730), Packing::Padded);
+    }
+
+    fn compile(patterns: &[&str], tags: &[(&str, &[&str])]) -> Result<BlockMatcher> {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        let tags = tags.iter()
+            .map(|(tag, patterns)| (tag.to_string(), patterns.iter().map(|pattern| pattern.to_string()).collect()))
+            .collect();
+        BlockMatcher::new(&patterns, &tags)
+    }
+
+    fn is_match(matcher: &BlockMatcher, state: &str) -> bool {
+        matcher.is_match(&state.parse().unwrap())
+    }
+
+    #[test]
+    fn matches_block_globs() {
+        let matcher = compile(&["minecraft:*_air", "stone"], &[]).unwrap();
+        assert!(is_match(&matcher, "minecraft:cave_air"));
+        assert!(is_match(&matcher, "minecraft:void_air[waterlogged=false]"));
+        assert!(is_match(&matcher, "minecraft:stone"));
+        assert!(!is_match(&matcher, "minecraft:air"));
+        assert!(!is_match(&matcher, "minecraft:stone_bricks"));
+    }
+
+    #[test]
+    fn matches_block_states() {
+        let matcher = compile(&["minecraft:water[level=0]", "*_slab[type=double,waterlogged=false]"], &[]).unwrap();
+        assert!(is_match(&matcher, "minecraft:water[level=0]"));
+        assert!(is_match(&matcher, "minecraft:oak_slab[type=double,waterlogged=false]"));
+        assert!(!is_match(&matcher, "minecraft:water[level=3]"));
+        assert!(!is_match(&matcher, "minecraft:water"));
+        assert!(!is_match(&matcher, "minecraft:oak_slab[type=double,waterlogged=true]"));
+        assert!(!is_match(&matcher, "minecraft:lava[level=0]"));
+    }
+
+    #[test]
+    fn resolves_nested_tags() {
+        let tags: &[(&str, &[&str])] = &[
+            ("air", &["minecraft:*air"]),
+            ("filler", &["#air", "minecraft:water[level=0]"]),
+        ];
+        let matcher = compile(&["#filler"], tags).unwrap();
+        assert!(is_match(&matcher, "minecraft:cave_air"));
+        assert!(is_match(&matcher, "minecraft:water[level=0]"));
+        assert!(!is_match(&matcher, "minecraft:stone"));
+
+        assert!(compile(&["#unknown"], tags).is_err());
+    }
+
+    #[test]
+    fn rejects_recursive_tags() {
+        let tags: &[(&str, &[&str])] = &[("a", &["minecraft:air", "#b"]), ("b", &["#a"])];
+        let error = compile(&["#a"], tags).unwrap_err();
+        assert_eq!(error.to_string(), "block tag '#a' references itself");
+
+        // A tag referenced twice without a cycle is fine
+        let tags: &[(&str, &[&str])] = &[("a", &["minecraft:air"]), ("b", &["#a", "#a"])];
+        assert!(compile(&["#b", "#a"], tags).is_ok());
+    }
+}
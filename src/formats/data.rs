@@ -0,0 +1,64 @@
+use fastnbt::LongArray;
+use serde::{Deserialize, Serialize};
+
+use super::nbt::{NbtFormat, Compound};
+
+impl NbtFormat for ForcedChunks {}
+impl NbtFormat for Raids {}
+impl NbtFormat for RandomSequences {}
+
+/// Chunks forced with `/forceload` (`data/chunks.dat`).
+#[derive(Serialize, Deserialize)]
+pub struct ForcedChunks {
+    pub data: ForcedChunksData,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ForcedChunksData {
+    #[serde(rename = "Forced", skip_serializing_if = "Option::is_none")]
+    pub forced: Option<LongArray>,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+impl ForcedChunks {
+    /// Get the forced chunk coordinates.
+    pub fn positions(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.data.forced.iter().flat_map(|forced| forced.iter())
+            .map(|pos| (*pos as i32, (*pos >> 32) as i32))
+    }
+}
+
+/// Ongoing raids of a dimension (`data/raids.dat`).
+#[derive(Serialize, Deserialize)]
+pub struct Raids {
+    pub data: RaidsData,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RaidsData {
+    #[serde(rename = "Raids", default)]
+    pub raids: Vec<Compound>,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+/// Random sequences used by loot tables and commands (`data/random_sequences.dat`).
+#[derive(Serialize, Deserialize)]
+pub struct RandomSequences {
+    pub data: RandomSequencesData,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RandomSequencesData {
+    #[serde(default)]
+    pub sequences: Compound,
+    #[serde(flatten)]
+    other: Compound,
+}
@@ -1,3 +1,4 @@
+use fastnbt::{IntArray, Value};
 use serde::{Deserialize, Serialize};
 
 use super::nbt::{NbtFormat, Compound};
@@ -18,6 +19,40 @@ pub struct Data {
     pub player: Compound,
     #[serde(rename = "DataPacks")]
     pub datapacks: DataPacks,
+    #[serde(rename = "GameRules", skip_serializing_if = "Option::is_none")]
+    pub game_rules: Option<Compound>,
+    #[serde(rename = "GameType", skip_serializing_if = "Option::is_none")]
+    pub game_type: Option<i32>,
+    #[serde(rename = "Difficulty", skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<i8>,
+    #[serde(rename = "DifficultyLocked", skip_serializing_if = "Option::is_none")]
+    pub difficulty_locked: Option<i8>,
+    #[serde(rename = "allowCommands", skip_serializing_if = "Option::is_none")]
+    pub allow_commands: Option<i8>,
+    #[serde(rename = "SpawnX", skip_serializing_if = "Option::is_none")]
+    pub spawn_x: Option<i32>,
+    #[serde(rename = "SpawnY", skip_serializing_if = "Option::is_none")]
+    pub spawn_y: Option<i32>,
+    #[serde(rename = "SpawnZ", skip_serializing_if = "Option::is_none")]
+    pub spawn_z: Option<i32>,
+    #[serde(rename = "spawn", skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<Spawn>,
+    #[serde(rename = "DayTime", skip_serializing_if = "Option::is_none")]
+    pub day_time: Option<i64>,
+    #[serde(rename = "Time", skip_serializing_if = "Option::is_none")]
+    pub time: Option<i64>,
+    #[serde(rename = "raining", skip_serializing_if = "Option::is_none")]
+    pub raining: Option<i8>,
+    #[serde(rename = "rainTime", skip_serializing_if = "Option::is_none")]
+    pub rain_time: Option<i32>,
+    #[serde(rename = "thundering", skip_serializing_if = "Option::is_none")]
+    pub thundering: Option<i8>,
+    #[serde(rename = "thunderTime", skip_serializing_if = "Option::is_none")]
+    pub thunder_time: Option<i32>,
+    #[serde(rename = "WorldGenSettings", skip_serializing_if = "Option::is_none")]
+    pub world_gen_settings: Option<Compound>,
+    #[serde(rename = "RandomSeed", skip_serializing_if = "Option::is_none")]
+    pub random_seed: Option<i64>,
     #[serde(flatten)]
     other: Compound,
 }
@@ -30,6 +65,14 @@ pub struct DataPacks {
     pub disabled: Vec<String>,
 }
 
+/// Respawn location used since 1.21.9 instead of `SpawnX`, `SpawnY` and `SpawnZ`.
+#[derive(Serialize, Deserialize)]
+pub struct Spawn {
+    pub pos: IntArray,
+    #[serde(flatten)]
+    other: Compound,
+}
+
 impl Level {
     pub fn walk_datapacks(&mut self, callback: fn(&mut String)) {
         self.walk_enabled_datapacks(callback);
@@ -47,4 +90,120 @@ impl Level {
             callback(datapack);
         }
     }
+
+    pub fn set_spawn(&mut self, x: i32, y: i32, z: i32) {
+        match &mut self.data.spawn {
+            Some(spawn) => spawn.pos = IntArray::new(vec![x, y, z]),
+            None => {
+                self.data.spawn_x = Some(x);
+                self.data.spawn_y = Some(y);
+                self.data.spawn_z = Some(z);
+            },
+        }
+    }
+
+    /// Replace the world seed (`RandomSeed` before 1.16, `WorldGenSettings` since).
+    /// Returns false when the level has neither of them.
+    pub fn set_seed(&mut self, seed: i64) -> bool {
+        if self.data.random_seed.is_some() {
+            self.data.random_seed = Some(seed);
+        }
+
+        let Some(settings) = &mut self.data.world_gen_settings else {
+            return self.data.random_seed.is_some();
+        };
+
+        settings.insert("seed".to_owned(), Value::Long(seed));
+        // Before 1.18 the seed is also duplicated in each dimension generator
+        if let Some(Value::Compound(dimensions)) = settings.get_mut("dimensions") {
+            for dimension in dimensions.values_mut() {
+                let Value::Compound(dimension) = dimension else { continue };
+                let Some(Value::Compound(generator)) = dimension.get_mut("generator") else { continue };
+                if generator.contains_key("seed") {
+                    generator.insert("seed".to_owned(), Value::Long(seed));
+                }
+                if let Some(Value::Compound(biome_source)) = generator.get_mut("biome_source") {
+                    if biome_source.contains_key("seed") {
+                        biome_source.insert("seed".to_owned(), Value::Long(seed));
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn clear_weather(&mut self) {
+        self.data.raining = Some(0);
+        self.data.rain_time = Some(0);
+        self.data.thundering = Some(0);
+        self.data.thunder_time = Some(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+
+    use super::*;
+
+    fn parse(data: Value) -> Level {
+        let Value::Compound(mut data) = data else { unreachable!() };
+        data.insert("LevelName".to_owned(), Value::String("demo".to_owned()));
+        data.insert("Player".to_owned(), Value::Compound(Compound::new()));
+        data.insert("DataPacks".to_owned(), nbt!({"Enabled": ["vanilla"], "Disabled": []}));
+        fastnbt::from_value(&nbt!({"Data": data})).unwrap()
+    }
+
+    #[test]
+    fn sets_the_seed_of_world_gen_settings() {
+        let mut level = parse(nbt!({"WorldGenSettings": {
+            "seed": 1_i64,
+            "dimensions": {
+                "minecraft:overworld": {"generator": {"seed": 1_i64, "biome_source": {"seed": 1_i64}}},
+                "minecraft:the_end": {"generator": {"type": "minecraft:flat"}},
+            },
+        }}));
+        assert!(level.set_seed(42));
+
+        let settings = fastnbt::to_value(level.data.world_gen_settings.unwrap()).unwrap();
+        assert_eq!(settings, nbt!({
+            "seed": 42_i64,
+            "dimensions": {
+                "minecraft:overworld": {"generator": {"seed": 42_i64, "biome_source": {"seed": 42_i64}}},
+                "minecraft:the_end": {"generator": {"type": "minecraft:flat"}},
+            },
+        }));
+        assert!(level.data.random_seed.is_none());
+    }
+
+    #[test]
+    fn sets_the_legacy_random_seed() {
+        let mut level = parse(nbt!({"RandomSeed": 1_i64}));
+        assert!(level.set_seed(42));
+        assert_eq!(level.data.random_seed, Some(42));
+        assert!(level.data.world_gen_settings.is_none());
+
+        let mut unseeded = parse(nbt!({}));
+        assert!(!unseeded.set_seed(42));
+        assert!(unseeded.data.random_seed.is_none());
+    }
+
+    #[test]
+    fn sets_the_legacy_spawn() {
+        let mut level = parse(nbt!({"SpawnX": 0, "SpawnY": 64, "SpawnZ": 0}));
+        level.set_spawn(12, 70, -30);
+        assert_eq!((level.data.spawn_x, level.data.spawn_y, level.data.spawn_z), (Some(12), Some(70), Some(-30)));
+        assert!(level.data.spawn.is_none());
+    }
+
+    #[test]
+    fn sets_the_spawn_compound() {
+        let spawn = nbt!({"pos": [I; 0, 64, 0], "dimension": "minecraft:overworld", "yaw": 0.0_f32});
+        let mut level = parse(nbt!({"spawn": spawn}));
+        level.set_spawn(12, 70, -30);
+        assert!(level.data.spawn_x.is_none());
+        let spawn = fastnbt::to_value(level.data.spawn.unwrap()).unwrap();
+        assert_eq!(spawn, nbt!({"pos": [I; 12, 70, -30], "dimension": "minecraft:overworld", "yaw": 0.0_f32}));
+    }
 }
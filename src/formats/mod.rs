@@ -1,9 +1,22 @@
+pub use anvil::{RawChunk, Scheme};
+pub use block::{BlockMatcher, BlockState};
+pub use data::{ForcedChunks, Raids, RandomSequences};
 pub use level::Level;
-pub use nbt::NbtFormat;
-pub use region::Region;
+pub use nbt::{diff, to_json, walk_compounds, Compound, NbtChange, NbtFormat, NbtPath};
+pub use region::{
+    ChunkDecision, ChunkDecisions, ChunkError, ChunkSet, Criterion, Emptiness, OptimizedRegion, ProcessedRegion,
+    Region, RegionStats,
+};
 pub use scoreboard::Scoreboard;
+pub use storage::CommandStorage;
 
+pub mod snbt;
+
+pub(crate) mod anvil;
+mod block;
+mod data;
 mod level;
 mod nbt;
 mod region;
 mod scoreboard;
+mod storage;
@@ -1,26 +1,41 @@
 use std::collections::HashMap;
-use std::fs::File;
+use std::fmt::{self, Display};
 use std::io::{Read, Write};
 use std::path::Path;
+use std::str::FromStr;
 
-use anyhow::Result;
-use flate2::read::GzDecoder;
+use anyhow::{anyhow, bail, Error, Result};
+use fastnbt::Value;
+use flate2::read::{GzDecoder, ZlibDecoder};
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use serde::{Deserialize, Deserializer};
 
 pub type Compound = HashMap<String, fastnbt::Value>;
 
+impl NbtFormat for Value {}
+
 pub trait NbtFormat {
     fn load(from: &Path) -> Result<Self>
     where
         Self: Sized + serde::de::DeserializeOwned,
     {
-        let file = File::open(from)?;
-        let mut decoder = GzDecoder::new(file);
-        let mut bytes = vec![];
-        decoder.read_to_end(&mut bytes)?;
+        Self::from_bytes(&std::fs::read(from)?)
+    }
+
+    /// Read gzip, zlib or uncompressed nbt (guessed from the first bytes).
+    fn from_bytes(bytes: &[u8]) -> Result<Self>
+    where
+        Self: Sized + serde::de::DeserializeOwned,
+    {
+        let mut data = vec![];
+        let bytes = match bytes {
+            [0x1f, 0x8b, ..] => { GzDecoder::new(bytes).read_to_end(&mut data)?; &data },
+            [0x78, ..] => { ZlibDecoder::new(bytes).read_to_end(&mut data)?; &data },
+            _ => bytes,
+        };
 
-        Ok(fastnbt::from_bytes(&bytes)?)
+        Ok(fastnbt::from_bytes(bytes)?)
     }
 
     fn to_bytes(&self) -> Result<Vec<u8>>
@@ -34,3 +49,374 @@ pub trait NbtFormat {
         Ok(encoder.finish()?)
     }
 }
+
+/// Recursively merge compounds, any other value is replaced.
+pub fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Compound(base), Value::Compound(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => { base.insert(key, value); },
+                }
+            }
+        },
+        (base, value) => *base = value,
+    }
+}
+
+/// Call the visitor on every compound nested in the value.
+pub fn walk_compounds(value: &Value, visitor: &mut impl FnMut(&Compound)) {
+    match value {
+        Value::Compound(compound) => {
+            visitor(compound);
+            compound.values().for_each(|value| walk_compounds(value, visitor));
+        },
+        Value::List(list) => list.iter().for_each(|value| walk_compounds(value, visitor)),
+        _ => {},
+    }
+}
+
+/// Difference found at a path when comparing two nbt values.
+pub enum NbtChange {
+    Added(NbtPath, Value),
+    Removed(NbtPath, Value),
+    Changed(NbtPath, Value, Value),
+}
+
+/// Compare two nbt values recursively (compounds by key and lists by index).
+pub fn diff(old: &Value, new: &Value) -> Vec<NbtChange> {
+    let mut changes = vec![];
+    diff_at(&mut vec![], old, new, &mut changes);
+    changes
+}
+
+fn diff_at(path: &mut Vec<PathSegment>, old: &Value, new: &Value, changes: &mut Vec<NbtChange>) {
+    match (old, new) {
+        (Value::Compound(old), Value::Compound(new)) => {
+            let mut keys: Vec<_> = old.keys().chain(new.keys().filter(|key| !old.contains_key(*key))).collect();
+            keys.sort();
+            for key in keys {
+                path.push(PathSegment::Key(key.to_owned()));
+                match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => diff_at(path, old, new, changes),
+                    (Some(old), None) => changes.push(NbtChange::Removed(NbtPath(path.clone()), old.clone())),
+                    (None, Some(new)) => changes.push(NbtChange::Added(NbtPath(path.clone()), new.clone())),
+                    (None, None) => {},
+                }
+                path.pop();
+            }
+        },
+        (Value::List(old), Value::List(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                path.push(PathSegment::Index(index));
+                match (old.get(index), new.get(index)) {
+                    (Some(old), Some(new)) => diff_at(path, old, new, changes),
+                    (Some(old), None) => changes.push(NbtChange::Removed(NbtPath(path.clone()), old.clone())),
+                    (None, Some(new)) => changes.push(NbtChange::Added(NbtPath(path.clone()), new.clone())),
+                    (None, None) => {},
+                }
+                path.pop();
+            }
+        },
+        (old, new) if old != new => changes.push(NbtChange::Changed(NbtPath(path.clone()), old.clone(), new.clone())),
+        _ => {},
+    }
+}
+
+/// Convert a nbt value to json (numeric types and array kinds are lost).
+pub fn to_json(value: &Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    let float = |v: f64| serde_json::Number::from_f64(v).map_or(Json::Null, Json::Number);
+    match value {
+        Value::Byte(v) => Json::from(*v),
+        Value::Short(v) => Json::from(*v),
+        Value::Int(v) => Json::from(*v),
+        Value::Long(v) => Json::from(*v),
+        Value::Float(v) => float(*v as f64),
+        Value::Double(v) => float(*v),
+        Value::String(v) => Json::from(v.as_str()),
+        Value::ByteArray(v) => Json::from(v.iter().copied().collect::<Vec<_>>()),
+        Value::IntArray(v) => Json::from(v.iter().copied().collect::<Vec<_>>()),
+        Value::LongArray(v) => Json::from(v.iter().copied().collect::<Vec<_>>()),
+        Value::List(list) => Json::Array(list.iter().map(to_json).collect()),
+        Value::Compound(compound) => Json::Object(compound.iter().map(|(k, v)| (k.to_owned(), to_json(v))).collect()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Path to a nested nbt value (e.g. `Data.DataPacks.Enabled[0]`).
+#[derive(Clone, Debug)]
+pub struct NbtPath(Vec<PathSegment>);
+
+impl NbtPath {
+    pub fn get<'a>(&self, mut value: &'a Value) -> Option<&'a Value> {
+        for segment in &self.0 {
+            value = match (segment, value) {
+                (PathSegment::Key(key), Value::Compound(compound)) => compound.get(key)?,
+                (PathSegment::Index(index), Value::List(list)) => list.get(*index)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    pub fn get_mut<'a>(&self, mut value: &'a mut Value) -> Option<&'a mut Value> {
+        for segment in &self.0 {
+            value = match (segment, value) {
+                (PathSegment::Key(key), Value::Compound(compound)) => compound.get_mut(key)?,
+                (PathSegment::Index(index), Value::List(list)) => list.get_mut(*index)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    /// Set the value, missing compounds along the path are created.
+    pub fn set(&self, mut root: &mut Value, value: Value) -> Result<()> {
+        let (last, parents) = self.0.split_last().ok_or_else(|| anyhow!("empty nbt path"))?;
+        for segment in parents {
+            root = match (segment, root) {
+                (PathSegment::Key(key), Value::Compound(compound)) => compound
+                    .entry(key.to_owned())
+                    .or_insert_with(|| Value::Compound(Compound::new())),
+                (PathSegment::Index(index), Value::List(list)) => list
+                    .get_mut(*index)
+                    .ok_or_else(|| anyhow!("index out of bounds at {self}"))?,
+                _ => bail!("could not set {self}"),
+            };
+        }
+
+        match (last, root) {
+            (PathSegment::Key(key), Value::Compound(compound)) => {
+                compound.insert(key.to_owned(), value);
+            },
+            (PathSegment::Index(index), Value::List(list)) if *index < list.len() => {
+                list[*index] = value;
+            },
+            _ => bail!("could not set {self}"),
+        }
+
+        Ok(())
+    }
+
+    /// Merge the value into the existing one (see [`merge`]).
+    pub fn merge(&self, root: &mut Value, value: Value) -> Result<()> {
+        match self.get_mut(root) {
+            Some(base) => {
+                merge(base, value);
+                Ok(())
+            },
+            None => self.set(root, value),
+        }
+    }
+
+    pub fn remove(&self, root: &mut Value) -> Option<Value> {
+        let (last, parents) = self.0.split_last()?;
+        match (last, NbtPath(parents.to_vec()).get_mut(root)?) {
+            (PathSegment::Key(key), Value::Compound(compound)) => compound.remove(key),
+            (PathSegment::Index(index), Value::List(list)) if *index < list.len() => {
+                Some(list.remove(*index))
+            },
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for NbtPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let mut segments = vec![];
+        let mut chars = path.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' if !segments.is_empty() => {},
+                '"' => {
+                    let mut key = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('\\') => key.extend(chars.next()),
+                            Some('"') => break,
+                            Some(c) => key.push(c),
+                            None => bail!("unterminated quote in nbt path '{path}'"),
+                        }
+                    }
+                    segments.push(PathSegment::Key(key));
+                },
+                '[' => {
+                    let mut index = String::new();
+                    while let Some(c) = chars.next_if(|c| *c != ']') {
+                        index.push(c);
+                    }
+                    chars.next().ok_or_else(|| anyhow!("unterminated index in nbt path '{path}'"))?;
+                    segments.push(PathSegment::Index(index.trim().parse().map_err(|_| {
+                        anyhow!("invalid index in nbt path '{path}'")
+                    })?));
+                },
+                c => {
+                    let mut key = String::from(c);
+                    while let Some(c) = chars.next_if(|c| !matches!(c, '.' | '[' | '"')) {
+                        key.push(c);
+                    }
+                    segments.push(PathSegment::Key(key));
+                },
+            }
+        }
+
+        match segments.is_empty() {
+            true => bail!("empty nbt path"),
+            false => Ok(Self(segments)),
+        }
+    }
+}
+
+impl Display for NbtPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("(root)");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+                PathSegment::Key(key) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    match key.contains(['.', '[', '"']) {
+                        true => write!(f, "{key:?}")?,
+                        false => f.write_str(key)?,
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for NbtPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+
+    use super::*;
+    use crate::formats::snbt;
+
+    fn path(path: &str) -> NbtPath {
+        path.parse().unwrap()
+    }
+
+    fn changes(old: &Value, new: &Value) -> Vec<String> {
+        diff(old, new).into_iter().map(|change| match change {
+            NbtChange::Added(path, value) => format!("+ {path}: {}", snbt::to_string(&value)),
+            NbtChange::Removed(path, value) => format!("- {path}: {}", snbt::to_string(&value)),
+            NbtChange::Changed(path, old, new) => {
+                format!("~ {path}: {} -> {}", snbt::to_string(&old), snbt::to_string(&new))
+            },
+        }).collect()
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(path("Data.Player").0, [PathSegment::Key("Data".into()), PathSegment::Key("Player".into())]);
+        assert_eq!(
+            path(r#"Data."minecraft:a.b"[2].c"#).0,
+            [
+                PathSegment::Key("Data".into()),
+                PathSegment::Key("minecraft:a.b".into()),
+                PathSegment::Index(2),
+                PathSegment::Key("c".into()),
+            ],
+        );
+        assert_eq!(path(r#""a\"b""#).0, [PathSegment::Key("a\"b".into())]);
+        assert!("".parse::<NbtPath>().is_err());
+        assert!("a[x]".parse::<NbtPath>().is_err());
+        assert!("a[1".parse::<NbtPath>().is_err());
+        assert!("\"a".parse::<NbtPath>().is_err());
+    }
+
+    #[test]
+    fn displays_paths() {
+        for value in ["Data.Player", "a[0][1].b", r#"Data."a.b"[2]"#] {
+            assert_eq!(path(value).to_string(), value);
+        }
+    }
+
+    #[test]
+    fn gets_values() {
+        let value = nbt!({"Data": {"List": [{"id": "a"}, {"id": "b"}]}});
+        assert_eq!(path("Data.List[1].id").get(&value), Some(&nbt!("b")));
+        assert_eq!(path("Data.List[2]").get(&value), None);
+        assert_eq!(path("Data.List.id").get(&value), None);
+    }
+
+    #[test]
+    fn sets_values() {
+        let mut value = nbt!({"Data": {"List": [1, 2]}});
+        path("Data.New.Nested").set(&mut value, nbt!(1i8)).unwrap();
+        path("Data.List[1]").set(&mut value, nbt!(3)).unwrap();
+        assert_eq!(value, nbt!({"Data": {"List": [1, 3], "New": {"Nested": 1i8}}}));
+        assert!(path("Data.List[2]").set(&mut value, nbt!(4)).is_err());
+        assert!(path("Data.List.a").set(&mut value, nbt!(4)).is_err());
+    }
+
+    #[test]
+    fn merges_values() {
+        let mut value = nbt!({"Data": {"a": 1, "b": {"c": 2}}});
+        path("Data").merge(&mut value, nbt!({"b": {"d": 3}, "e": 4})).unwrap();
+        path("Data.f").merge(&mut value, nbt!(5)).unwrap();
+        assert_eq!(value, nbt!({"Data": {"a": 1, "b": {"c": 2, "d": 3}, "e": 4, "f": 5}}));
+    }
+
+    #[test]
+    fn removes_values() {
+        let mut value = nbt!({"Data": {"a": 1, "List": [1, 2, 3]}});
+        assert_eq!(path("Data.List[1]").remove(&mut value), Some(nbt!(2)));
+        assert_eq!(path("Data.a").remove(&mut value), Some(nbt!(1)));
+        assert_eq!(path("Data.missing").remove(&mut value), None);
+        assert_eq!(path("Data.List[5]").remove(&mut value), None);
+        assert_eq!(value, nbt!({"Data": {"List": [1, 3]}}));
+    }
+
+    #[test]
+    fn diffs_compounds_by_key() {
+        let old = nbt!({"Data": {"b": 1, "a": "x", "gone": 1i8, "same": [1, 2]}});
+        let new = nbt!({"Data": {"b": 2i64, "a": "x", "new": {"c": 1}, "same": [1, 2]}});
+        assert_eq!(changes(&old, &new), [
+            "~ Data.b: 1 -> 2L",
+            "- Data.gone: 1b",
+            "+ Data.new: {c:1}",
+        ]);
+    }
+
+    #[test]
+    fn diffs_lists_by_index() {
+        let old = nbt!({"List": [{"id": "a"}, {"id": "b"}], "Short": [1]});
+        let new = nbt!({"List": [{"id": "a"}, {"id": "c"}, {"id": "d"}], "Short": []});
+        assert_eq!(changes(&old, &new), [
+            "~ List[1].id: \"b\" -> \"c\"",
+            "+ List[2]: {id:\"d\"}",
+            "- Short[0]: 1",
+        ]);
+    }
+
+    #[test]
+    fn diffs_equal_and_root_values() {
+        let value = nbt!({"a": [B; 1, 2], "b": [{"c": 1.5}]});
+        assert!(diff(&value, &value.clone()).is_empty());
+        assert_eq!(changes(&nbt!([I; 1]), &nbt!([I; 2])), ["~ (root): [I;1] -> [I;2]"]);
+    }
+}
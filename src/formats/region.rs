@@ -1,28 +1,183 @@
-use std::fs::File;
-use std::io::Cursor;
-use std::path::Path;
-
-use anyhow::Result;
-use fastanvil::ChunkData;
-use fastanvil::Region as Chunks;
-use fastnbt::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use fastnbt::{LongArray, Value};
 use serde::{Deserialize, Serialize};
 
-use crate::Config;
+use crate::{BiomeOverride, Config, CorruptPolicy, EntityFilter, Recompression, StripSettings};
+use super::anvil::{self, RawChunk, Scheme};
+use super::block::{dedupe_palette, BlockState, Packing};
 use super::nbt::Compound;
 
+/// Set of chunk positions (in absolute chunk coordinates).
+pub type ChunkSet = HashSet<(i32, i32)>;
+
+/// First data version supporting lz4 compressed chunks (1.20.5).
+const LZ4_DATA_VERSION: i32 = 3837;
+
+/// Emptiness of chunks by position (in absolute chunk coordinates).
+pub type ChunkDecisions = HashMap<(i32, i32), Emptiness>;
+
 pub struct Region {
-    pub chunks: Chunks<File>,
+    pub bytes: Vec<u8>,
+    pub path: PathBuf,
+    /// Region coordinates from the file name (`r.<x>.<z>.mca`).
+    pub position: Option<(i32, i32)>,
+}
+
+/// Region bytes once processed with the decision taken for every chunk.
+pub struct OptimizedRegion {
+    pub bytes: Vec<u8>,
+    /// External chunk files (`c.<x>.<z>.mcc`) to write next to the region.
+    pub external: Vec<(String, Vec<u8>)>,
+    /// Corrupt chunks that were skipped or kept as is.
+    pub corrupt: Vec<ChunkError>,
+    pub chunks: Vec<ChunkDecision>,
+    /// Number of chunks older than 1.20.5 that kept their scheme instead of lz4.
+    pub legacy_lz4: usize,
+}
+
+/// Region with every chunk processed once, optimized afterwards with the decisions
+/// shared by the region kinds of its dimension.
+pub struct ProcessedRegion {
+    position: Option<(i32, i32)>,
+    /// Processed chunks with their emptiness (only decided when cleaning chunks).
+    chunks: Vec<(RawChunk, Option<Emptiness>)>,
+    corrupt: Vec<ChunkError>,
+    legacy_lz4: usize,
+}
+
+/// Processed chunk (`None` when untouched) with its emptiness.
+type ProcessedChunk = (Option<RawChunk>, Option<Emptiness>);
+
+/// Decision taken for a chunk (in absolute chunk coordinates).
+pub struct ChunkDecision {
+    pub x: i32,
+    pub z: i32,
+    pub emptiness: Emptiness,
+}
+
+/// Chunk that could not be read or parsed (in absolute chunk coordinates).
+#[derive(Debug)]
+pub struct ChunkError {
+    pub x: i32,
+    pub z: i32,
+    pub kept: bool,
+    pub error: Error,
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "corrupt chunk {} {} ({:#})", self.x, self.z, self.error)
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// Content of a region gathered for inspection.
+#[derive(Default)]
+pub struct RegionStats {
+    pub chunks: usize,
+    pub corrupt: usize,
+    /// Number of sections using each block in their palette.
+    pub blocks: HashMap<String, usize>,
+    /// Number of entities by id (passengers included).
+    pub entities: HashMap<String, usize>,
+}
+
+/// Explain whether a chunk is empty, with every criterion keeping it otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Emptiness {
+    Incomplete,
+    Empty,
+    Kept(Vec<Criterion>),
+}
+
+/// Criterion keeping a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Criterion {
+    Forced,
+    BlockEntities,
+    Entities,
+    Poi,
+    Ticks,
+    Blocks,
+    Biomes,
+    Unsupported,
+}
+
+impl Emptiness {
+    fn from_criteria(criteria: Vec<Criterion>) -> Self {
+        match criteria.is_empty() {
+            true => Self::Empty,
+            false => Self::Kept(criteria),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !matches!(self, Self::Kept(_))
+    }
+
+    /// Combine the emptiness of the same chunk from another region kind, kept if any is kept.
+    pub fn merge(&mut self, other: Self) {
+        match (self, other) {
+            (Self::Kept(criteria), Self::Kept(other)) => {
+                for criterion in other {
+                    if !criteria.contains(&criterion) {
+                        criteria.push(criterion);
+                    }
+                }
+            },
+            (Self::Kept(_), _) | (Self::Incomplete, Self::Empty) => {},
+            (this, other) => *this = other,
+        }
+    }
+}
+
+impl fmt::Display for Emptiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Incomplete => f.write_str("not fully generated"),
+            Self::Empty => f.write_str("only ignored blocks and biomes"),
+            Self::Kept(criteria) => {
+                let criteria: Vec<_> = criteria.iter().map(Criterion::to_string).collect();
+                f.write_str(&criteria.join(", "))
+            },
+        }
+    }
 }
 
+impl fmt::Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Forced => "forced chunk",
+            Self::BlockEntities => "has block entities",
+            Self::Entities => "has entities",
+            Self::Poi => "has points of interest",
+            Self::Ticks => "has pending ticks",
+            Self::Blocks => "has blocks",
+            Self::Biomes => "has biomes",
+            Self::Unsupported => "unsupported chunk format",
+        })
+    }
+}
+
+/// Chunk of any region kind, 1.18+ chunks use the top-level fields while 1.13 to 1.17 chunks
+/// are nested in `Level` (see `Chunk::data_version`), the edits support both layouts.
 #[derive(Serialize, Deserialize)]
 struct Chunk {
+    #[serde(rename = "DataVersion")]
+    pub data_version: Option<i32>,
     #[serde(rename = "Sections")]
-    pub poi: Option<Compound>,
+    pub poi_sections: Option<Compound>,
     #[serde(rename = "Entities")]
     pub entities: Option<Vec<Compound>>,
     pub sections: Option<Vec<Section>>,
     pub block_entities: Option<Vec<Compound>>,
+    #[serde(rename = "Level")]
+    pub legacy: Option<LegacyChunk>,
     #[serde(flatten)]
     other: Compound,
 }
@@ -30,73 +185,840 @@ struct Chunk {
 #[derive(Serialize, Deserialize)]
 struct Section {
     pub block_states: Option<BlockStates>,
+    pub biomes: Option<Biomes>,
     #[serde(flatten)]
     other: Compound,
 }
 
 #[derive(Serialize, Deserialize)]
 struct BlockStates {
-    pub palette: Vec<PaletteItem>,
+    pub palette: Vec<BlockState>,
+    pub data: Option<LongArray>,
     #[serde(flatten)]
     other: Compound,
 }
 
 #[derive(Serialize, Deserialize)]
-struct PaletteItem {
-    #[serde(rename = "Name")]
-    pub name: String,
-    #[serde(rename = "Properties")]
-    pub properties: Option<Value>,
+struct Biomes {
+    pub palette: Vec<String>,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+/// Chunk data of 1.13 to 1.17 worlds (older sections without palette are kept as is).
+#[derive(Serialize, Deserialize)]
+struct LegacyChunk {
+    #[serde(rename = "Status")]
+    pub status: Option<String>,
+    #[serde(rename = "Sections")]
+    pub sections: Option<Vec<LegacySection>>,
+    #[serde(rename = "Entities")]
+    pub entities: Option<Vec<Compound>>,
+    #[serde(rename = "TileEntities")]
+    pub block_entities: Option<Vec<Compound>>,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LegacySection {
+    #[serde(rename = "Palette")]
+    pub palette: Option<Vec<BlockState>>,
+    #[serde(rename = "BlockStates")]
+    pub data: Option<LongArray>,
+    #[serde(flatten)]
+    other: Compound,
 }
 
 impl Chunk {
-    pub fn new(data: &ChunkData) -> Result<Chunk> {
-        Ok(fastnbt::from_bytes(&data.data)?)
-    }
-
-    pub fn is_empty(&self, ignored_blocks: &[String]) -> bool {
-        if self.other.get("Status").map_or(false, |v| v != "full" && v != "minecraft:full") {
-            return true;
-        }
-
-        let is_not_empty = self.block_entities.as_ref().map_or(false, |chunk| !chunk.is_empty())
-            || self.entities.as_ref().map_or(false, |chunk| !chunk.is_empty())
-            || self.poi.as_ref().map_or(false, |chunk| !chunk.is_empty())
-            || self.sections.as_ref().map_or(false, |chunk| {
-                for section in chunk.iter() {
-                    if let Some(block_states) = &section.block_states {
-                        for item in &block_states.palette {
-                            if !ignored_blocks.contains(&item.name) {
-                                return true;
-                            }
-                        }
-                    }
-                }
-                false
-            });
+    pub fn from_raw(raw: &RawChunk) -> Result<Chunk> {
+        Ok(fastnbt::from_bytes(&raw.decompress()?)?)
+    }
+
+    /// Remove the entities (and passengers) matching any of the filters.
+    pub fn remove_entities(&mut self, filters: &[EntityFilter]) {
+        if filters.is_empty() {
+            return;
+        }
+        let legacy = self.legacy.as_mut().and_then(|legacy| legacy.entities.as_mut());
+        for entities in self.entities.iter_mut().chain(legacy) {
+            entities.retain_mut(|entity| retain_entity(entity, filters));
+        }
+    }
 
-        !is_not_empty
+    /// Remove, clear or strip the block entities according to the config.
+    pub fn scrub_block_entities(&mut self, config: &Config) {
+        let legacy = self.legacy.as_mut().and_then(|legacy| legacy.block_entities.as_mut());
+        for block_entities in self.block_entities.iter_mut().chain(legacy) {
+            block_entities.retain_mut(|entity| scrub_block_entity(entity, config));
+        }
+    }
+
+    /// Replace the palette entries matching any of the rules (first match wins).
+    /// Block indices are repacked according to the chunk layout (and the data version of legacy chunks).
+    pub fn replace_blocks(&mut self, rules: &[(BlockState, BlockState)]) {
+        if rules.is_empty() {
+            return;
+        }
+        for block_states in self.sections.iter_mut().flatten().filter_map(|section| section.block_states.as_mut()) {
+            replace_palette(&mut block_states.palette, &mut block_states.data, rules, Packing::Modern);
+        }
+        let packing = Packing::from_legacy_version(self.data_version.unwrap_or_default());
+        for section in self.legacy.iter_mut().filter_map(|legacy| legacy.sections.as_mut()).flatten() {
+            if let Some(palette) = &mut section.palette {
+                replace_palette(palette, &mut section.data, rules, packing);
+            }
+        }
+    }
+
+    /// Get the chunk coordinates (from `xPos` and `zPos`).
+    pub fn position(&self) -> Option<(i32, i32)> {
+        let chunk = self.legacy.as_ref().map_or(&self.other, |legacy| &legacy.other);
+        match (chunk.get("xPos"), chunk.get("zPos")) {
+            (Some(Value::Int(x)), Some(Value::Int(z))) => Some((*x, *z)),
+            _ => None,
+        }
+    }
+
+    /// Force a single biome in every section, unless the chunk overlaps the excepted area.
+    /// Only 1.18+ chunks are supported (legacy biomes are stored as numeric ids).
+    pub fn override_biomes(&mut self, biome_override: &BiomeOverride) {
+        if let (Some(area), Some((x, z))) = (&biome_override.except, self.position()) {
+            if area.overlaps_chunk(x, z) {
+                return;
+            }
+        }
+        for biomes in self.sections.iter_mut().flatten().filter_map(|section| section.biomes.as_mut()) {
+            biomes.palette = vec![biome_override.biome.to_owned()];
+            biomes.other.remove("data");
+        }
+    }
+
+    /// Count the sections using each block and the entities by id.
+    pub fn count(&self, stats: &mut RegionStats) {
+        let palettes = self.sections.iter().flatten()
+            .filter_map(|section| section.block_states.as_ref().map(|block_states| &block_states.palette));
+        let legacy_palettes = self.legacy.iter()
+            .flat_map(|legacy| legacy.sections.iter().flatten())
+            .filter_map(|section| section.palette.as_ref());
+        for palette in palettes.chain(legacy_palettes) {
+            for name in palette.iter().map(|item| &item.name).collect::<HashSet<_>>() {
+                *stats.blocks.entry(name.to_owned()).or_default() += 1;
+            }
+        }
+
+        let legacy_entities = self.legacy.iter().flat_map(|legacy| legacy.entities.iter().flatten());
+        for entity in self.entities.iter().flatten().chain(legacy_entities) {
+            count_entity(entity, &mut stats.entities);
+        }
+    }
+
+    /// Apply the chunk edits deciding the emptiness, biomes are only overridden afterwards
+    /// so that ignored biomes are checked against the original ones.
+    pub fn edit(&mut self, config: &Config) {
+        self.remove_entities(&config.removed_entities);
+        self.scrub_block_entities(config);
+        self.replace_blocks(&config.replace_blocks);
+        self.strip(&config.strip_chunk_data);
+    }
+
+    /// Strip the chunk data according to the settings.
+    pub fn strip(&mut self, strip: &StripSettings) {
+        let sections = self.sections.iter_mut().flatten().map(|section| &mut section.other);
+        strip_chunk(&mut self.other, sections.collect(), strip);
+
+        if let Some(legacy) = &mut self.legacy {
+            let sections = legacy.sections.iter_mut().flatten().map(|section| &mut section.other);
+            strip_chunk(&mut legacy.other, sections.collect(), strip);
+        }
+    }
+
+    /// Explain whether the chunk is empty (forced chunks are always kept).
+    pub fn emptiness(&self, config: &Config, forced: bool) -> Emptiness {
+        let complete = match &self.legacy {
+            Some(legacy) => legacy.is_complete(),
+            None => self.other.get("Status").is_none_or(|v| v == "full" || v == "minecraft:full"),
+        };
+        if !complete && !forced {
+            return Emptiness::Incomplete;
+        }
+
+        let mut criteria = match &self.legacy {
+            Some(legacy) => legacy.criteria(config),
+            None => self.modern_criteria(config),
+        };
+        if forced {
+            criteria.insert(0, Criterion::Forced);
+        }
+        Emptiness::from_criteria(criteria)
+    }
+
+    fn modern_criteria(&self, config: &Config) -> Vec<Criterion> {
+        let sections = self.sections.iter().flatten();
+        [
+            (Criterion::BlockEntities, has_items(&self.block_entities)),
+            (Criterion::Entities, has_items(&self.entities)),
+            (Criterion::Poi, self.poi_sections.as_ref().is_some_and(|poi| !poi.is_empty())),
+            (Criterion::Ticks, has_ticks(&self.other, &["block_ticks", "fluid_ticks"])),
+            (Criterion::Blocks, sections.clone().filter_map(|section| section.block_states.as_ref())
+                .any(|block_states| block_states.palette.iter().any(|item| !config.ignored_blocks.is_match(item)))),
+            (Criterion::Biomes, sections.filter_map(|section| section.biomes.as_ref())
+                .any(|biomes| biomes.palette.iter().any(|biome| !config.ignored_biomes.is_match(biome)))),
+        ].into_iter().filter_map(|(criterion, applies)| applies.then_some(criterion)).collect()
     }
 }
 
+impl LegacyChunk {
+    fn is_complete(&self) -> bool {
+        self.status.as_deref().is_none_or(is_full_status)
+    }
+
+    fn criteria(&self, config: &Config) -> Vec<Criterion> {
+        let sections = self.sections.iter().flatten();
+        [
+            (Criterion::BlockEntities, has_items(&self.block_entities)),
+            (Criterion::Entities, has_items(&self.entities)),
+            (Criterion::Ticks, has_ticks(&self.other, &["TileTicks", "LiquidTicks"])),
+            (Criterion::Unsupported, sections.clone()
+                .any(|section| section.palette.is_none() && section.other.contains_key("Blocks"))),
+            (Criterion::Blocks, sections.filter_map(|section| section.palette.as_ref())
+                .any(|palette| palette.iter().any(|item| !config.ignored_blocks.is_match(item)))),
+        ].into_iter().filter_map(|(criterion, applies)| applies.then_some(criterion)).collect()
+    }
+}
+
+fn has_items(list: &Option<Vec<Compound>>) -> bool {
+    list.as_ref().is_some_and(|list| !list.is_empty())
+}
+
+fn has_ticks(chunk: &Compound, keys: &[&str]) -> bool {
+    keys.iter().any(|key| match chunk.get(*key) {
+        Some(Value::List(ticks)) => !ticks.is_empty(),
+        _ => false,
+    })
+}
+
+/// Check if the legacy status is a fully generated chunk (1.13 used `postprocessed` and `fullchunk`).
+fn is_full_status(status: &str) -> bool {
+    matches!(status.trim_start_matches("minecraft:"), "full" | "fullchunk" | "postprocessed")
+}
+
+fn replace_palette(
+    palette: &mut Vec<BlockState>,
+    data: &mut Option<LongArray>,
+    rules: &[(BlockState, BlockState)],
+    packing: Packing,
+) {
+    let mut replaced = false;
+    for item in palette.iter_mut() {
+        if let Some((from, to)) = rules.iter().find(|(from, _)| from.matches(item)) {
+            // Keep the other properties of the block (e.g. the facing of stairs)
+            let mut properties = std::mem::take(&mut item.properties);
+            properties.retain(|key, _| !from.properties.contains_key(key));
+            properties.extend(to.properties.clone());
+            *item = BlockState { name: to.name.clone(), properties };
+            replaced = true;
+        }
+    }
+    if replaced {
+        dedupe_palette(palette, data, packing);
+    }
+}
+
+fn strip_chunk(chunk: &mut Compound, sections: Vec<&mut Compound>, strip: &StripSettings) {
+    if strip.light {
+        for section in sections {
+            section.remove("SkyLight");
+            section.remove("BlockLight");
+        }
+        for key in ["isLightOn", "LightPopulated"] {
+            if chunk.contains_key(key) {
+                chunk.insert(key.to_owned(), Value::Byte(0));
+            }
+        }
+    }
+    if strip.heightmaps {
+        chunk.remove("Heightmaps");
+        chunk.remove("HeightMap");
+    }
+    if strip.inhabited_time && chunk.contains_key("InhabitedTime") {
+        chunk.insert("InhabitedTime".to_owned(), Value::Long(0));
+    }
+    if strip.ticks {
+        for key in ["block_ticks", "fluid_ticks", "TileTicks", "LiquidTicks", "ToBeTicked", "LiquidsToBeTicked"] {
+            chunk.remove(key);
+        }
+    }
+    if strip.post_processing {
+        chunk.remove("PostProcessing");
+    }
+    if strip.structures {
+        for key in ["structures", "Structures"] {
+            if let Some(Value::Compound(structures)) = chunk.get_mut(key) {
+                structures.clear();
+            }
+        }
+    }
+    if strip.carving_masks {
+        chunk.remove("CarvingMasks");
+    }
+}
+
+fn scrub_block_entity(entity: &mut Compound, config: &Config) -> bool {
+    let id = match entity.get("id") {
+        Some(Value::String(id)) => id.as_str(),
+        _ => "",
+    };
+    if config.removed_block_entities.is_match(id) {
+        return false;
+    }
+    if config.strip_command_output && id.ends_with("command_block") {
+        entity.remove("LastOutput");
+    }
+    if entity.contains_key("Items") && config.cleared_containers.iter().any(|e| e.matches(entity)) {
+        entity.insert("Items".to_owned(), Value::List(vec![]));
+    }
+
+    true
+}
+
+fn count_entity(entity: &Compound, counts: &mut HashMap<String, usize>) {
+    if let Some(Value::String(id)) = entity.get("id") {
+        *counts.entry(id.to_owned()).or_default() += 1;
+    }
+    if let Some(Value::List(passengers)) = entity.get("Passengers") {
+        for passenger in passengers {
+            if let Value::Compound(passenger) = passenger {
+                count_entity(passenger, counts);
+            }
+        }
+    }
+}
+
+fn retain_entity(entity: &mut Compound, filters: &[EntityFilter]) -> bool {
+    if filters.iter().any(|filter| filter.matches(entity)) {
+        return false;
+    }
+    if let Some(Value::List(passengers)) = entity.get_mut("Passengers") {
+        retain_entities(passengers, filters);
+    }
+
+    true
+}
+
+fn retain_entities(entities: &mut Vec<Value>, filters: &[EntityFilter]) {
+    entities.retain_mut(|entity| match entity {
+        Value::Compound(entity) => retain_entity(entity, filters),
+        _ => true,
+    });
+}
+
 impl Region {
     pub fn load(from: &Path) -> Result<Self> {
-        let chunks = Chunks::from_stream(File::open(from)?)?;
+        Ok(Self::from_bytes(std::fs::read(from)?, from))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>, path: &Path) -> Self {
+        Self { bytes, path: path.to_owned(), position: Self::position(path) }
+    }
+
+    /// Get the region coordinates from the file name (`r.<x>.<z>.mca`).
+    pub fn position(path: &Path) -> Option<(i32, i32)> {
+        let mut parts = path.file_name()?.to_str()?.strip_prefix("r.")?.split('.');
+        Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+    }
+
+    /// Get the external chunk files (`c.<x>.<z>.mcc`) belonging to the region file.
+    pub fn external_files(path: &Path) -> Vec<PathBuf> {
+        let Some(position) = Self::position(path) else {
+            return vec![];
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+        std::fs::read_dir(dir).into_iter().flatten().filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let mut parts = name.strip_prefix("c.")?.strip_suffix(".mcc")?.split('.');
+            let (x, z): (i32, i32) = (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+            (position == (x >> 5, z >> 5)).then_some(path)
+        }).collect()
+    }
+
+    pub fn chunks(&self) -> Result<impl Iterator<Item = Result<RawChunk, ChunkError>> + '_> {
+        self.chunks_with(|path| Ok(std::fs::read(path)?))
+    }
+
+    /// Iterate over the chunks, external chunk files are read with the given function (e.g. from a storage).
+    /// Corrupt chunks are reported in absolute chunk coordinates.
+    pub fn chunks_with<'a, F>(&'a self, read: F) -> Result<impl Iterator<Item = Result<RawChunk, ChunkError>> + 'a>
+    where
+        F: Fn(&Path) -> Result<Vec<u8>> + 'a,
+    {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        let chunks = anvil::read_chunks(&self.bytes, move |x, z| {
+            let (x, z) = self.absolute_position(x, z);
+            let path = dir.join(external_name(x, z));
+            read(&path).with_context(|| format!("could not read external chunk [{}]", path.display()))
+        })?;
+
+        Ok(chunks.map(|raw| raw.map_err(|chunk| {
+            let (x, z) = self.absolute_position(chunk.x, chunk.z);
+            ChunkError { x, z, kept: false, error: chunk.error }
+        })))
+    }
+
+    /// Process every chunk and explain whether it is empty, chunks in the forced set are never removed.
+    /// Corrupt chunks have no decision unless the corrupt chunk policy aborts.
+    pub fn decide(&self, config: &Config, forced: &ChunkSet) -> Result<Vec<ChunkDecision>> {
+        let mut decisions = vec![];
+        for raw in self.chunks()? {
+            let decision = raw.map_err(Error::from).and_then(|raw| {
+                let (x, z) = self.absolute_position(raw.x, raw.z);
+                let mut chunk = Chunk::from_raw(&raw).with_context(|| format!("corrupt chunk {x} {z}"))?;
+                chunk.edit(config);
+                Ok(ChunkDecision { x, z, emptiness: chunk.emptiness(config, forced.contains(&(x, z))) })
+            });
+            match decision {
+                Ok(decision) => decisions.push(decision),
+                Err(err) if config.corrupt_chunks == CorruptPolicy::Abort => return Err(err),
+                Err(_) => {},
+            }
+        }
+
+        Ok(decisions)
+    }
+
+    /// Count the chunks, palette entries and entities of the region (corrupt chunks are only counted).
+    pub fn stats(&self) -> Result<RegionStats> {
+        let mut stats = RegionStats::default();
+        for raw in self.chunks()? {
+            match raw.map_err(Error::from).and_then(|raw| Chunk::from_raw(&raw)) {
+                Ok(chunk) => {
+                    stats.chunks += 1;
+                    chunk.count(&mut stats);
+                },
+                Err(_) => stats.corrupt += 1,
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Process every chunk once: edit, decide its emptiness (chunks in the forced set are never removed),
+    /// override its biomes and recompress it if needed (untouched chunks keep their payload).
+    /// Corrupt chunks are skipped, kept as is or abort the processing depending on the config.
+    pub fn process(&self, config: &Config, forced: &ChunkSet) -> Result<ProcessedRegion> {
+        let mut chunks = vec![];
+        let mut corrupt = vec![];
+        let mut legacy_lz4 = 0;
+        let lz4 = config.recompress_regions.as_ref().is_some_and(|recompression| {
+            recompression.compression == Some(Scheme::Lz4)
+        });
+        let mut report = |x: i32, z: i32, kept: bool, error: Error| {
+            match config.corrupt_chunks {
+                CorruptPolicy::Abort => Err(error.context(format!("corrupt chunk {x} {z}"))),
+                _ => {
+                    corrupt.push(ChunkError { x, z, kept, error });
+                    Ok(())
+                },
+            }
+        };
+
+        for raw in self.chunks()? {
+            let raw = match raw {
+                Ok(raw) => raw,
+                Err(chunk) => {
+                    report(chunk.x, chunk.z, false, chunk.error)?;
+                    continue;
+                },
+            };
+
+            match self.process_chunk(&raw, config, forced) {
+                Ok((chunk, emptiness)) => {
+                    let chunk = chunk.unwrap_or(raw);
+                    if lz4 && chunk.scheme != Scheme::Lz4 {
+                        legacy_lz4 += 1;
+                    }
+                    chunks.push((chunk, emptiness));
+                },
+                Err(error) => {
+                    let (x, z) = self.absolute_position(raw.x, raw.z);
+                    let kept = config.corrupt_chunks == CorruptPolicy::Keep;
+                    if kept {
+                        chunks.push((raw, None));
+                    }
+                    report(x, z, kept, error)?;
+                },
+            }
+        }
+
+        Ok(ProcessedRegion { position: self.position, chunks, corrupt, legacy_lz4 })
+    }
+
+    /// Process the chunk, returns no chunk when untouched (still parsed to detect corrupt chunks).
+    fn process_chunk(&self, raw: &RawChunk, config: &Config, forced: &ChunkSet) -> Result<ProcessedChunk> {
+        let (scheme, level) = match &config.recompress_regions {
+            Some(recompression) => (recompression.compression.unwrap_or(raw.scheme), recompression.level),
+            None => (raw.scheme, None),
+        };
+
+        let data = raw.decompress()?;
+        let mut chunk: Chunk = fastnbt::from_bytes(&data)?;
+        let scheme = match scheme {
+            Scheme::Lz4 if chunk.data_version.is_none_or(|version| version < LZ4_DATA_VERSION) => raw.scheme,
+            scheme => scheme,
+        };
+        chunk.edit(config);
+        let emptiness = config.clean_chunks.then(|| {
+            chunk.emptiness(config, forced.contains(&self.absolute_position(raw.x, raw.z)))
+        });
+        if let Some(biome_override) = &config.biome_override {
+            chunk.override_biomes(biome_override);
+        }
+
+        let data = match (config.edit_chunks(), scheme == raw.scheme && level.is_none()) {
+            (false, true) => return Ok((None, emptiness)),
+            (true, _) => fastnbt::to_bytes(&chunk)?,
+            (false, false) => data,
+        };
+
+        let level = level.unwrap_or(Recompression::DEFAULT_LEVEL);
+        Ok((Some(RawChunk::compress(raw.x, raw.z, raw.timestamp, &data, scheme, level)?), emptiness))
+    }
+
+    /// Check if any chunk is stored in an external chunk file (`c.<x>.<z>.mcc`).
+    pub fn has_external_chunks(&self) -> bool {
+        anvil::has_external_chunks(&self.bytes)
+    }
+
+    /// Get the absolute chunk coordinates from the coordinates in the region.
+    pub fn absolute_position(&self, x: usize, z: usize) -> (i32, i32) {
+        absolute_position(self.position, x, z)
+    }
+}
+
+impl ProcessedRegion {
+    /// Get the emptiness of the decided chunks.
+    pub fn decisions(&self) -> impl Iterator<Item = ChunkDecision> + '_ {
+        self.chunks.iter().filter_map(|(raw, emptiness)| {
+            let (x, z) = absolute_position(self.position, raw.x, raw.z);
+            emptiness.clone().map(|emptiness| ChunkDecision { x, z, emptiness })
+        })
+    }
+
+    /// Remove the chunks decided as empty (chunks without decision are kept) and rewrite the region compactly.
+    pub fn optimize(self, decisions: &ChunkDecisions) -> OptimizedRegion {
+        let mut decided = vec![];
+        let chunks: Vec<_> = self.chunks.into_iter().map(|(raw, _)| raw).filter(|raw| {
+            let (x, z) = absolute_position(self.position, raw.x, raw.z);
+            match decisions.get(&(x, z)) {
+                Some(emptiness) => {
+                    decided.push(ChunkDecision { x, z, emptiness: emptiness.clone() });
+                    !emptiness.is_empty()
+                },
+                None => true,
+            }
+        }).collect();
+
+        let (bytes, external) = anvil::write_chunks(&chunks);
+        OptimizedRegion {
+            bytes,
+            external: external.into_iter().map(|raw| {
+                let (x, z) = absolute_position(self.position, raw.x, raw.z);
+                (external_name(x, z), raw.payload.to_owned())
+            }).collect(),
+            chunks: decided,
+            corrupt: self.corrupt,
+            legacy_lz4: self.legacy_lz4,
+        }
+    }
+}
+
+fn absolute_position(position: Option<(i32, i32)>, x: usize, z: usize) -> (i32, i32) {
+    let (region_x, region_z) = position.unwrap_or_default();
+    (region_x * 32 + x as i32, region_z * 32 + z as i32)
+}
+
+fn external_name(x: i32, z: i32) -> String {
+    format!("c.{x}.{z}.mcc")
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+    use globset::{Glob, GlobSetBuilder};
+
+    use super::*;
+
+    fn chunk(block: &str, biome: &str, status: &str) -> Chunk {
+        fastnbt::from_value(&nbt!({
+            "DataVersion": 3465,
+            "Status": status,
+            "sections": [{
+                "block_states": { "palette": [{ "Name": block }] },
+                "biomes": { "palette": [biome] },
+            }],
+        })).unwrap()
+    }
+
+    #[test]
+    fn removes_empty_chunks() {
+        let config = Config::default();
+        let air = chunk("minecraft:air", "minecraft:the_void", "minecraft:full");
+        assert_eq!(air.emptiness(&config, false), Emptiness::Empty);
+        let incomplete = chunk("minecraft:stone", "minecraft:plains", "minecraft:noise");
+        assert_eq!(incomplete.emptiness(&config, false), Emptiness::Incomplete);
+    }
+
+    #[test]
+    fn keeps_chunks_with_custom_biomes_by_default() {
+        let chunk = chunk("minecraft:air", "minecraft:plains", "minecraft:full");
+        assert_eq!(chunk.emptiness(&Config::default(), false), Emptiness::Kept(vec![Criterion::Biomes]));
+    }
+
+    #[test]
+    fn lists_every_criterion() {
+        let chunk = chunk("minecraft:stone", "minecraft:plains", "minecraft:noise");
+        assert_eq!(
+            chunk.emptiness(&Config::default(), true),
+            Emptiness::Kept(vec![Criterion::Forced, Criterion::Blocks, Criterion::Biomes]),
+        );
+    }
+
+    #[test]
+    fn reports_corrupt_chunks_in_absolute_coordinates() {
+        let mut bytes = vec![0; 8192];
+        let index = 3 + 4 * 32;
+        bytes[index * 4..index * 4 + 4].copy_from_slice(&(5 << 8 | 1_u32).to_be_bytes());
+        let region = Region::from_bytes(bytes, Path::new("region/r.-1.2.mca"));
+
+        let Some(Err(chunk)) = region.chunks().unwrap().next() else {
+            panic!("the chunk is corrupt");
+        };
+        assert_eq!((chunk.x, chunk.z), (-29, 68));
+        assert!(chunk.to_string().starts_with("corrupt chunk -29 68"));
+    }
+
+    #[test]
+    fn merges_emptiness() {
+        let mut emptiness = Emptiness::Empty;
+        emptiness.merge(Emptiness::Incomplete);
+        assert_eq!(emptiness, Emptiness::Incomplete);
+        emptiness.merge(Emptiness::Kept(vec![Criterion::Blocks]));
+        emptiness.merge(Emptiness::Kept(vec![Criterion::Entities, Criterion::Blocks]));
+        emptiness.merge(Emptiness::Empty);
+        assert_eq!(emptiness, Emptiness::Kept(vec![Criterion::Blocks, Criterion::Entities]));
+        assert_eq!(emptiness.to_string(), "has blocks, has entities");
+    }
+
+    /// Region (`r.0.0.mca`) with a single zlib chunk compressed at the default level.
+    fn region(data_version: i32) -> Region {
+        let heights: Vec<i64> = (0..4096).map(|i| (i * i / 7) % 1000).collect();
+        let value = nbt!({"DataVersion": data_version, "Status": "minecraft:full", "Heightmaps": {"A": heights}});
+        let data = fastnbt::to_bytes(&value).unwrap();
+        let chunk = RawChunk::compress(0, 0, 0, &data, Scheme::Zlib, Recompression::DEFAULT_LEVEL).unwrap();
+        Region::from_bytes(anvil::write_chunks(&[chunk]).0, Path::new("region/r.0.0.mca"))
+    }
+
+    fn recompress(region: &Region, compression: Option<Scheme>, level: Option<u32>) -> ProcessedRegion {
+        let mut config = Config::default();
+        config.recompress_regions = Some(Recompression { compression, level });
+        region.process(&config, &ChunkSet::default()).unwrap()
+    }
+
+    #[test]
+    fn recompresses_with_the_configured_level() {
+        let region = region(3465);
+        let original = region.chunks().unwrap().next().unwrap().unwrap();
+
+        let kept = recompress(&region, None, None);
+        assert_eq!(kept.chunks[0].0.payload, original.payload);
+
+        let recompressed = recompress(&region, None, Some(9));
+        let (chunk, _) = &recompressed.chunks[0];
+        assert_eq!(chunk.scheme, Scheme::Zlib);
+        assert_ne!(chunk.payload, original.payload);
+        assert_eq!(chunk.decompress().unwrap(), original.decompress().unwrap());
+    }
+
+    #[test]
+    fn keeps_the_scheme_of_chunks_without_lz4() {
+        let legacy = recompress(&region(3465), Some(Scheme::Lz4), None);
+        assert_eq!(legacy.chunks[0].0.scheme, Scheme::Zlib);
+        assert_eq!(legacy.legacy_lz4, 1);
 
-        Ok(Self { chunks })
+        let modern = recompress(&region(LZ4_DATA_VERSION), Some(Scheme::Lz4), None);
+        assert_eq!(modern.chunks[0].0.scheme, Scheme::Lz4);
+        assert_eq!(modern.legacy_lz4, 0);
     }
 
-    pub fn optimize_bytes(&mut self, config: &Config) -> Result<Vec<u8>> {
-        let mut chunks = Chunks::new(Cursor::new(vec![]))?;
-        for data in self.chunks.iter() {
-            let data = &data?;
-            let chunk = &Chunk::new(data)?;
-            if !chunk.is_empty(&config.ignored_blocks) {
-                let ser = fastnbt::to_bytes(chunk)?;
-                chunks.write_chunk(data.x, data.z, &ser)?;
+    fn entity_ids(entities: &[Compound]) -> Vec<String> {
+        entities.iter().map(|entity| {
+            let passengers = match entity.get("Passengers") {
+                Some(Value::List(passengers)) => passengers.iter().filter_map(|passenger| match passenger {
+                    Value::Compound(passenger) => passenger.get("id").and_then(|id| id.as_str()),
+                    _ => None,
+                }).collect(),
+                _ => vec![],
+            };
+            let id = entity.get("id").and_then(|id| id.as_str()).unwrap_or_default();
+            let mut id = id.trim_start_matches("minecraft:").to_owned();
+            if entity.contains_key("CustomName") {
+                id.push_str(" (named)");
             }
+            match passengers.is_empty() {
+                true => id,
+                false => format!("{id} [{}]", passengers.join(",")),
+            }
+        }).collect()
+    }
+
+    const BOAT: &str = "boat [minecraft:zombie,minecraft:villager]";
+
+    fn entities() -> Value {
+        nbt!([
+            {"id": "minecraft:item"},
+            {"id": "minecraft:chest_minecart", "Tags": ["keep"]},
+            {"id": "minecraft:armor_stand", "Tags": ["debug", "marker"]},
+            {"id": "minecraft:armor_stand", "CustomName": "\"Guide\""},
+            {"id": "minecraft:boat", "Passengers": [{"id": "minecraft:zombie"}, {"id": "minecraft:villager"}]},
+        ])
+    }
+
+    fn remove_entities(filters: &str) -> (Vec<String>, Vec<String>) {
+        let filters: Vec<EntityFilter> = serde_yaml::from_str(filters).unwrap();
+        let mut modern: Chunk = fastnbt::from_value(&nbt!({"DataVersion": 3465, "Entities": entities()})).unwrap();
+        let legacy = nbt!({"DataVersion": 2586, "Level": {"Entities": entities()}});
+        let mut legacy: Chunk = fastnbt::from_value(&legacy).unwrap();
+        modern.remove_entities(&filters);
+        legacy.remove_entities(&filters);
+        let legacy = legacy.legacy.unwrap().entities.unwrap();
+        (entity_ids(&modern.entities.unwrap()), entity_ids(&legacy))
+    }
+
+    #[test]
+    fn removes_entities_by_id() {
+        let (modern, legacy) = remove_entities("[{id: minecraft:item}, {id: 'minecraft:*_minecart'}]");
+        assert_eq!(modern, ["armor_stand", "armor_stand (named)", BOAT]);
+        assert_eq!(legacy, modern);
+    }
+
+    #[test]
+    fn removes_entities_by_tag() {
+        let (modern, legacy) = remove_entities("[{tag: 'deb*'}]");
+        assert_eq!(modern, ["item", "chest_minecart", "armor_stand (named)", BOAT]);
+        assert_eq!(legacy, modern);
+    }
+
+    #[test]
+    fn removes_entities_by_custom_name() {
+        let (modern, legacy) = remove_entities("[{id: minecraft:armor_stand, custom_name: false}]");
+        assert_eq!(modern, ["item", "chest_minecart", "armor_stand (named)", BOAT]);
+        assert_eq!(legacy, modern);
+
+        let (modern, legacy) = remove_entities("[{custom_name: true}]");
+        assert_eq!(modern, ["item", "chest_minecart", "armor_stand", BOAT]);
+        assert_eq!(legacy, modern);
+    }
+
+    #[test]
+    fn removes_passengers() {
+        let (modern, legacy) = remove_entities("[{id: minecraft:zombie}]");
+        assert_eq!(modern.last().unwrap(), "boat [minecraft:villager]");
+        assert_eq!(legacy, modern);
+
+        let (modern, legacy) = remove_entities("[{id: minecraft:boat}]");
+        assert_eq!(modern, ["item", "chest_minecart", "armor_stand", "armor_stand (named)"]);
+        assert_eq!(legacy, modern);
+    }
+
+    fn block_entities() -> Value {
+        nbt!([
+            {"id": "minecraft:command_block", "x": 0, "y": 64, "z": 0, "Command": "say hi", "LastOutput": "\"hi\""},
+            {"id": "minecraft:chest", "x": 1, "y": 64, "z": 0, "Items": [{"id": "minecraft:stone"}]},
+            {"id": "minecraft:trapped_chest", "x": 2, "y": 64, "z": 0, "Items": [{"id": "minecraft:dirt"}]},
+            {"id": "minecraft:barrel", "x": 3, "y": 64, "z": 0, "Items": [{"id": "minecraft:sand"}]},
+            {"id": "minecraft:structure_block", "x": 4, "y": 64, "z": 0},
+        ])
+    }
+
+    fn scrub_block_entities(config: &Config) -> (Vec<Compound>, Vec<Compound>) {
+        let modern = nbt!({"DataVersion": 3465, "block_entities": block_entities()});
+        let mut modern: Chunk = fastnbt::from_value(&modern).unwrap();
+        let legacy = nbt!({"DataVersion": 2586, "Level": {"TileEntities": block_entities()}});
+        let mut legacy: Chunk = fastnbt::from_value(&legacy).unwrap();
+        modern.scrub_block_entities(config);
+        legacy.scrub_block_entities(config);
+        (modern.block_entities.unwrap(), legacy.legacy.unwrap().block_entities.unwrap())
+    }
+
+    fn filled_containers(block_entities: &[Compound]) -> Vec<&str> {
+        block_entities.iter()
+            .filter(|entity| matches!(entity.get("Items"), Some(Value::List(items)) if !items.is_empty()))
+            .filter_map(|entity| entity.get("id").and_then(|id| id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn strips_command_output() {
+        let mut config = Config::default();
+        config.strip_command_output = true;
+        let (modern, legacy) = scrub_block_entities(&config);
+        for block_entities in [modern, legacy] {
+            assert!(!block_entities[0].contains_key("LastOutput"));
+            assert!(block_entities[0].contains_key("Command"));
         }
+    }
+
+    #[test]
+    fn clears_containers() {
+        let mut config = Config::default();
+        config.cleared_containers = serde_yaml::from_str("[{id: 'minecraft:*chest'}]").unwrap();
+        let (modern, legacy) = scrub_block_entities(&config);
+        assert_eq!(filled_containers(&modern), ["minecraft:barrel"]);
+        assert_eq!(filled_containers(&legacy), ["minecraft:barrel"]);
+
+        config.cleared_containers = serde_yaml::from_str("[{pos: [2, 64, 0]}]").unwrap();
+        let (modern, legacy) = scrub_block_entities(&config);
+        assert_eq!(filled_containers(&modern), ["minecraft:chest", "minecraft:barrel"]);
+        assert_eq!(filled_containers(&legacy), ["minecraft:chest", "minecraft:barrel"]);
+    }
+
+    #[test]
+    fn removes_block_entities_by_id() {
+        let mut config = Config::default();
+        let mut builder = GlobSetBuilder::new();
+        builder.add(Glob::new("minecraft:structure_*").unwrap());
+        config.removed_block_entities = builder.build().unwrap();
+        let (modern, legacy) = scrub_block_entities(&config);
+        for block_entities in [modern, legacy] {
+            let ids: Vec<_> = block_entities.iter().filter_map(|entity| entity.get("id")?.as_str()).collect();
+            let kept = ["minecraft:command_block", "minecraft:chest", "minecraft:trapped_chest", "minecraft:barrel"];
+            assert_eq!(ids, kept);
+            assert_eq!(block_entities[0].get("LastOutput").unwrap().as_str(), Some("\"hi\""));
+        }
+    }
+
+    #[test]
+    fn replaces_blocks_and_keeps_their_properties() {
+        let palette = nbt!([
+            {"Name": "minecraft:oak_stairs", "Properties": {"facing": "east", "waterlogged": "true"}},
+            {"Name": "minecraft:stone_stairs", "Properties": {"facing": "east", "waterlogged": "false"}},
+            {"Name": "minecraft:barrier", "Properties": {"waterlogged": "true"}},
+            {"Name": "minecraft:water", "Properties": {"level": "0"}},
+        ]);
+        // 4 bits per block: indices 0, 1, 2 and 3 repeated
+        let data = LongArray::new(vec![0x3210_3210_3210_3210; 256]);
+        let chunk = nbt!({"DataVersion": 3465, "sections": [{"block_states": {"palette": palette, "data": data}}]});
+        let mut chunk: Chunk = fastnbt::from_value(&chunk).unwrap();
+        let rules = [
+            ("minecraft:oak_stairs", "minecraft:stone_stairs[waterlogged=false]"),
+            ("minecraft:barrier[waterlogged=true]", "minecraft:water[level=0]"),
+        ];
+        chunk.replace_blocks(&rules.map(|(from, to)| (from.parse().unwrap(), to.parse().unwrap())));
 
-        Ok(chunks.into_inner()?.into_inner())
+        let block_states = chunk.sections.unwrap().remove(0).block_states.unwrap();
+        let palette: Vec<_> = block_states.palette.iter().map(ToString::to_string).collect();
+        assert_eq!(palette, ["minecraft:stone_stairs[facing=east,waterlogged=false]", "minecraft:water[level=0]"]);
+        assert!(block_states.data.unwrap().iter().all(|&long| long == 0x1100_1100_1100_1100));
     }
 }
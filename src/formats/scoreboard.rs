@@ -1,3 +1,6 @@
+use std::collections::{HashMap, HashSet};
+
+use fastnbt::Value;
 use serde::{Deserialize, Serialize};
 
 use super::nbt::{NbtFormat, Compound};
@@ -17,6 +20,10 @@ pub struct Data {
     pub scores: Vec<Score>,
     #[serde(rename = "Objectives")]
     pub objectives: Vec<Objective>,
+    #[serde(rename = "Teams", default)]
+    pub teams: Vec<Team>,
+    #[serde(rename = "DisplaySlots", skip_serializing_if = "Option::is_none")]
+    pub display_slots: Option<HashMap<String, String>>,
     #[serde(flatten)]
     other: Compound,
 }
@@ -25,10 +32,25 @@ pub struct Data {
 pub struct Score {
     #[serde(rename = "Name")]
     pub name: String,
+    #[serde(rename = "Objective")]
+    pub objective: String,
+    #[serde(rename = "Score")]
+    pub value: i32,
     #[serde(flatten)]
     other: Compound,
 }
 
+impl Score {
+    pub fn new(name: &str, objective: &str, value: i32) -> Self {
+        Self {
+            name: name.to_owned(),
+            objective: objective.to_owned(),
+            value,
+            other: Compound::from([("Locked".to_owned(), Value::Byte(0))]),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Objective {
     #[serde(rename = "Name")]
@@ -36,3 +58,91 @@ pub struct Objective {
     #[serde(flatten)]
     other: Compound,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct Team {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+impl Scoreboard {
+    pub fn has_objective(&self, objective: &str) -> bool {
+        self.data.objectives.iter().any(|e| e.name == objective)
+    }
+
+    pub fn set_score(&mut self, name: &str, objective: &str, value: i32) {
+        match self.data.scores.iter_mut().find(|e| e.name == name && e.objective == objective) {
+            Some(score) => score.value = value,
+            None => self.data.scores.push(Score::new(name, objective, value)),
+        }
+    }
+
+    pub fn reset_score(&mut self, name: &str, objective: &str) {
+        self.data.scores.retain(|e| e.name != name || e.objective != objective);
+    }
+
+    /// Remove the scores and display slots referencing a missing objective.
+    pub fn remove_orphans(&mut self) {
+        let objectives: HashSet<_> = self.data.objectives.iter().map(|e| e.name.to_owned()).collect();
+        self.data.scores.retain(|e| objectives.contains(&e.objective));
+        if let Some(slots) = &mut self.data.display_slots {
+            slots.retain(|_, objective| objectives.contains(objective));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+
+    use super::*;
+
+    fn scores(scoreboard: &Scoreboard) -> Vec<(&str, &str, i32)> {
+        scoreboard.data.scores.iter().map(|e| (e.name.as_str(), e.objective.as_str(), e.value)).collect()
+    }
+
+    #[test]
+    fn sets_and_resets_scores() {
+        let mut scoreboard: Scoreboard = fastnbt::from_value(&nbt!({
+            "DataVersion": 3465_i16,
+            "data": {
+                "PlayerScores": [
+                    {"Name": "#version", "Objective": "config", "Score": 2, "Locked": 0_i8},
+                    {"Name": "#debug", "Objective": "config", "Score": 1, "Locked": 0_i8},
+                    {"Name": "#debug", "Objective": "stats", "Score": 5, "Locked": 0_i8},
+                ],
+                "Objectives": [{"Name": "config"}, {"Name": "stats"}],
+            },
+        })).unwrap();
+
+        scoreboard.set_score("#version", "config", 3);
+        scoreboard.set_score("#seed", "config", 42);
+        scoreboard.reset_score("#debug", "config");
+        scoreboard.reset_score("#missing", "config");
+        assert_eq!(scores(&scoreboard), [("#version", "config", 3), ("#debug", "stats", 5), ("#seed", "config", 42)]);
+
+        let created = fastnbt::to_value(scoreboard.data.scores.last().unwrap()).unwrap();
+        assert_eq!(created, nbt!({"Name": "#seed", "Objective": "config", "Score": 42, "Locked": 0_i8}));
+    }
+
+    #[test]
+    fn removes_orphan_scores() {
+        let mut scoreboard: Scoreboard = fastnbt::from_value(&nbt!({
+            "DataVersion": 3465_i16,
+            "data": {
+                "PlayerScores": [
+                    {"Name": "alex", "Objective": "kills", "Score": 3},
+                    {"Name": "alex", "Objective": "removed", "Score": 1},
+                ],
+                "Objectives": [{"Name": "kills"}],
+                "DisplaySlots": {"sidebar": "removed", "list": "kills"},
+            },
+        })).unwrap();
+
+        scoreboard.remove_orphans();
+        assert_eq!(scores(&scoreboard), [("alex", "kills", 3)]);
+        assert_eq!(scoreboard.data.display_slots.unwrap(), HashMap::from([("list".to_owned(), "kills".to_owned())]));
+    }
+}
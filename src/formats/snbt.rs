@@ -0,0 +1,370 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use anyhow::{anyhow, bail, Result};
+use fastnbt::{ByteArray, IntArray, LongArray, Value};
+
+use super::nbt::Compound;
+
+/// Parse a stringified nbt value (e.g. `{Count: 1b, id: "minecraft:stone"}`).
+pub fn parse(input: &str) -> Result<Value> {
+    let mut parser = Parser { chars: input.chars().peekable() };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+
+    match parser.chars.next() {
+        Some(c) => bail!("unexpected character '{c}' in snbt"),
+        None => Ok(value),
+    }
+}
+
+/// Stringify a nbt value on a single line, compound keys are sorted (e.g. `{Count:1b,id:"minecraft:stone"}`).
+pub fn to_string(value: &Value) -> String {
+    let mut output = String::new();
+    Printer { indent: None }.value(&mut output, value, 0);
+    output
+}
+
+/// Stringify a nbt value with compounds and nested lists spread on indented lines.
+pub fn to_string_pretty(value: &Value) -> String {
+    let mut output = String::new();
+    Printer { indent: Some(4) }.value(&mut output, value, 0);
+    output
+}
+
+struct Printer {
+    indent: Option<usize>,
+}
+
+impl Printer {
+    fn value(&self, output: &mut String, value: &Value, depth: usize) {
+        match value {
+            Value::Byte(v) => output.push_str(&format!("{v}b")),
+            Value::Short(v) => output.push_str(&format!("{v}s")),
+            Value::Int(v) => output.push_str(&v.to_string()),
+            Value::Long(v) => output.push_str(&format!("{v}L")),
+            Value::Float(v) => output.push_str(&format!("{}f", decimal(*v))),
+            Value::Double(v) => output.push_str(&format!("{}d", decimal(*v))),
+            Value::String(v) => output.push_str(&quote(v)),
+            Value::ByteArray(v) => array(output, 'B', v.iter().map(|v| format!("{v}b"))),
+            Value::IntArray(v) => array(output, 'I', v.iter().map(|v| v.to_string())),
+            Value::LongArray(v) => array(output, 'L', v.iter().map(|v| format!("{v}L"))),
+            Value::List(list) => {
+                let nested = list.iter().any(|v| matches!(v, Value::Compound(_) | Value::List(_)));
+                let printer = match nested {
+                    true => self,
+                    false => &Printer { indent: None },
+                };
+                printer.entries(output, ('[', ']'), list.iter().map(|v| (None, v)), depth);
+            },
+            Value::Compound(compound) => {
+                let mut entries: Vec<_> = compound.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+                self.entries(output, ('{', '}'), entries.into_iter().map(|(k, v)| (Some(k), v)), depth);
+            },
+        }
+    }
+
+    fn entries<'a>(
+        &self,
+        output: &mut String,
+        (open, close): (char, char),
+        entries: impl ExactSizeIterator<Item = (Option<&'a String>, &'a Value)>,
+        depth: usize,
+    ) {
+        output.push(open);
+        let empty = entries.len() == 0;
+        for (i, (key, value)) in entries.enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            if let Some(indent) = self.indent {
+                output.push('\n');
+                output.push_str(&" ".repeat(indent * (depth + 1)));
+            }
+            if let Some(key) = key {
+                match key.chars().all(is_unquoted) && !key.is_empty() {
+                    true => output.push_str(key),
+                    false => output.push_str(&quote(key)),
+                }
+                output.push_str(if self.indent.is_some() { ": " } else { ":" });
+            }
+            self.value(output, value, depth + 1);
+        }
+        if let (Some(indent), false) = (self.indent, empty) {
+            output.push('\n');
+            output.push_str(&" ".repeat(indent * depth));
+        }
+        output.push(close);
+    }
+}
+
+/// Format a finite decimal, infinities overflow when parsed back and NaN (which snbt cannot express) becomes 0.
+fn decimal<T: Copy + Into<f64> + std::fmt::Display>(v: T) -> String {
+    match v.into() {
+        f if f.is_nan() => "0".to_owned(),
+        f if f.is_infinite() => format!("{}1e1000", if f < 0.0 { "-" } else { "" }),
+        _ => v.to_string(),
+    }
+}
+
+fn array(output: &mut String, kind: char, values: impl Iterator<Item = String>) {
+    output.push_str(&format!("[{kind};{}]", values.collect::<Vec<_>>().join(",")));
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn is_unquoted(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-.+".contains(c)
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.compound().map(Value::Compound),
+            Some('[') => self.list(),
+            Some('"' | '\'') => self.quoted().map(Value::String),
+            Some(_) => Ok(literal(&self.unquoted()?)),
+            None => bail!("unexpected end of snbt"),
+        }
+    }
+
+    fn compound(&mut self) -> Result<Compound> {
+        self.expect('{')?;
+        let mut compound = Compound::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(compound);
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = match self.chars.peek() {
+                Some('"' | '\'') => self.quoted()?,
+                _ => self.unquoted()?,
+            };
+            self.skip_whitespace();
+            self.expect(':')?;
+            compound.insert(key, self.value()?);
+            if self.separator('}')? {
+                return Ok(compound);
+            }
+        }
+    }
+
+    fn list(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut lookahead = self.chars.clone();
+        if let (Some(kind @ ('B' | 'I' | 'L')), Some(';')) = (lookahead.next(), lookahead.next()) {
+            self.chars.next();
+            self.chars.next();
+            return self.array(kind);
+        }
+
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Value::List(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            if self.separator(']')? {
+                return Ok(Value::List(values));
+            }
+        }
+    }
+
+    fn array(&mut self, kind: char) -> Result<Value> {
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_none() {
+            loop {
+                self.skip_whitespace();
+                values.push(self.unquoted()?);
+                if self.separator(']')? {
+                    break;
+                }
+            }
+        }
+
+        let number = |value: &String| value.trim_end_matches(['b', 'B', 'l', 'L']).to_owned();
+        let invalid = |value: &String| anyhow!("invalid array element '{value}' in snbt");
+        Ok(match kind {
+            'B' => Value::ByteArray(ByteArray::new(values.iter().map(|v| {
+                number(v).parse().map_err(|_| invalid(v))
+            }).collect::<Result<_>>()?)),
+            'I' => Value::IntArray(IntArray::new(values.iter().map(|v| {
+                v.parse().map_err(|_| invalid(v))
+            }).collect::<Result<_>>()?)),
+            _ => Value::LongArray(LongArray::new(values.iter().map(|v| {
+                number(v).parse().map_err(|_| invalid(v))
+            }).collect::<Result<_>>()?)),
+        })
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        let quote = self.chars.next().unwrap_or_default();
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('\\') => value.push(self.chars.next().ok_or_else(|| anyhow!("unterminated string in snbt"))?),
+                Some(c) if c == quote => return Ok(value),
+                Some(c) => value.push(c),
+                None => bail!("unterminated string in snbt"),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> Result<String> {
+        let mut value = String::new();
+        while let Some(c) = self.chars.next_if(|c| is_unquoted(*c)) {
+            value.push(c);
+        }
+
+        match value.is_empty() {
+            true => bail!("expected a value in snbt"),
+            false => Ok(value),
+        }
+    }
+
+    /// Consume a comma or the closing character, returns true when closed.
+    fn separator(&mut self, close: char) -> Result<bool> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(',') => Ok(false),
+            Some(c) if c == close => Ok(true),
+            Some(c) => bail!("unexpected character '{c}' in snbt"),
+            None => bail!("unexpected end of snbt"),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => bail!("expected '{expected}' but found '{c}' in snbt"),
+            None => bail!("unexpected end of snbt"),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+}
+
+fn literal(value: &str) -> Value {
+    let (number, suffix) = match value.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&value[..i], Some(c.to_ascii_lowercase())),
+        _ => (value, None),
+    };
+
+    let parsed = match suffix {
+        Some('b') => number.parse().map(Value::Byte).ok(),
+        Some('s') => number.parse().map(Value::Short).ok(),
+        Some('l') => number.parse().map(Value::Long).ok(),
+        Some('f') => number.parse().map(Value::Float).ok(),
+        Some('d') => number.parse().map(Value::Double).ok(),
+        Some(_) => None,
+        None => number.parse().map(Value::Int).ok()
+            .or_else(|| number.parse().map(Value::Double).ok().filter(|_| number.contains(['.', 'e', 'E']))),
+    };
+
+    parsed.unwrap_or_else(|| match value {
+        "true" => Value::Byte(1),
+        "false" => Value::Byte(0),
+        _ => Value::String(value.to_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnbt::nbt;
+
+    use super::*;
+
+    #[test]
+    fn parses_typed_literals() {
+        assert_eq!(parse("1b").unwrap(), Value::Byte(1));
+        assert_eq!(parse("-2s").unwrap(), Value::Short(-2));
+        assert_eq!(parse("3").unwrap(), Value::Int(3));
+        assert_eq!(parse("4L").unwrap(), Value::Long(4));
+        assert_eq!(parse("0.5f").unwrap(), Value::Float(0.5));
+        assert_eq!(parse("1.5d").unwrap(), Value::Double(1.5));
+        assert_eq!(parse("2.5").unwrap(), Value::Double(2.5));
+        assert_eq!(parse("1e5").unwrap(), Value::Double(1e5));
+        assert_eq!(parse("1.5E-3").unwrap(), Value::Double(1.5e-3));
+        assert_eq!(parse("true").unwrap(), Value::Byte(1));
+        assert_eq!(parse("false").unwrap(), Value::Byte(0));
+        assert_eq!(parse("stone").unwrap(), Value::String("stone".to_owned()));
+        assert_eq!(parse("1x").unwrap(), Value::String("1x".to_owned()));
+    }
+
+    #[test]
+    fn parses_arrays() {
+        assert_eq!(parse("[B; 1b, -2b]").unwrap(), nbt!([B; 1, -2]));
+        assert_eq!(parse("[I;1,2,3]").unwrap(), nbt!([I; 1, 2, 3]));
+        assert_eq!(parse("[L; 1L, 2]").unwrap(), nbt!([L; 1, 2]));
+        assert_eq!(parse("[I;]").unwrap(), nbt!([I;]));
+        assert!(parse("[I; a]").is_err());
+    }
+
+    #[test]
+    fn parses_lists_and_compounds() {
+        assert_eq!(
+            parse("{Count: 1b, Items: [{id: \"minecraft:stone\"}, {}], Empty: []}").unwrap(),
+            nbt!({"Count": 1i8, "Items": [{"id": "minecraft:stone"}, {}], "Empty": []}),
+        );
+        assert!(parse("{a: 1").is_err());
+        assert!(parse("{a: 1} b").is_err());
+    }
+
+    #[test]
+    fn parses_quoted_keys_and_escapes() {
+        assert_eq!(
+            parse(r#"{"minecraft:key": 'it\'s', "a b": "say \"hi\" \\o/"}"#).unwrap(),
+            nbt!({"minecraft:key": "it's", "a b": "say \"hi\" \\o/"}),
+        );
+        assert!(parse("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn prints_and_parses_back() {
+        let values = [
+            nbt!({
+                "byte": 1i8, "short": -2i16, "int": 3, "long": 4i64, "float": 0.1f32, "double": -1.5e-7,
+                "bytes": [B; 1, -2], "ints": [I; 3], "longs": [L;], "list": [[1, 2], []],
+                "strings": ["1b", "true", "it's \"quoted\" \\", ""], "": {"a b": {}},
+            }),
+            Value::Float(f32::MAX),
+            Value::Double(f64::MIN_POSITIVE),
+            Value::Float(f32::INFINITY),
+            Value::Double(f64::NEG_INFINITY),
+        ];
+        for value in values {
+            assert_eq!(parse(&to_string(&value)).unwrap(), value);
+            assert_eq!(parse(&to_string_pretty(&value)).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn prints_non_finite_decimals() {
+        assert_eq!(to_string(&Value::Double(f64::INFINITY)), "1e1000d");
+        assert_eq!(to_string(&Value::Float(f32::NEG_INFINITY)), "-1e1000f");
+        assert_eq!(to_string(&Value::Double(f64::NAN)), "0d");
+        assert_eq!(to_string(&Value::Float(1.5)), "1.5f");
+    }
+
+    #[test]
+    fn prints_sorted_compounds() {
+        let value = nbt!({"b": [1, 2], "a": {"c": "minecraft:stone"}});
+        assert_eq!(to_string(&value), r#"{a:{c:"minecraft:stone"},b:[1,2]}"#);
+        assert_eq!(to_string_pretty(&value), "{\n    a: {\n        c: \"minecraft:stone\"\n    },\n    b: [1,2]\n}");
+    }
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+use fastnbt::Value;
+use serde::{Deserialize, Serialize};
+
+use super::nbt::{NbtFormat, Compound};
+
+impl NbtFormat for CommandStorage {}
+
+/// Command storage of a single namespace (`data/command_storage_<namespace>.dat`).
+#[derive(Serialize, Deserialize)]
+pub struct CommandStorage {
+    pub data: Data,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Data {
+    pub contents: HashMap<String, Value>,
+    #[serde(flatten)]
+    other: Compound,
+}
@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ignore::{WalkBuilder, WalkState};
+use rayon::prelude::*;
+
+use crate::entries::{self, Entry};
+use crate::formats::{ChunkSet, Level, NbtFormat, Region, RegionStats, Scoreboard};
+use crate::Config;
+
+const TOP_BLOCKS: usize = 15;
+const TOP_ENTITIES: usize = 15;
+
+/// Statistics of a world, gathered without modifying it.
+#[derive(Default)]
+pub struct Inspection {
+    /// Number of files and total size by entry kind.
+    pub entries: BTreeMap<&'static str, (usize, u64)>,
+    pub dimensions: BTreeMap<PathBuf, DimensionStats>,
+    /// Block and entity counts of every region (see `RegionStats`).
+    pub blocks: HashMap<String, usize>,
+    pub entities: HashMap<String, usize>,
+    /// Datapacks listed in the level.dat with their enabled state.
+    pub datapacks: Vec<(String, bool)>,
+    pub scoreboard: Option<ScoreboardStats>,
+}
+
+#[derive(Default)]
+pub struct DimensionStats {
+    /// Number of region files by kind (`region`, `entities` or `poi`).
+    pub regions: BTreeMap<String, usize>,
+    pub chunks: usize,
+    pub corrupt: usize,
+    /// Number of chunks that `clean_chunks` would remove.
+    pub removable: usize,
+}
+
+pub struct ScoreboardStats {
+    pub objectives: usize,
+    pub scores: usize,
+    pub teams: usize,
+}
+
+impl Inspection {
+    /// Inspect the world, chunks are decided with the given config as if `clean_chunks` was enabled.
+    pub fn run(config: &Config, world: &Path) -> Self {
+        std::env::set_current_dir(world).expect("could not set working dir");
+
+        let mut inspection = Self::default();
+        let mut regions = vec![];
+        for entry in world_entries() {
+            let size = entry_size(&entry);
+            let (count, total) = inspection.entries.entry(entry.kind()).or_default();
+            *count += 1;
+            *total += size;
+            if let Entry::Region(region) = entry {
+                regions.push(region.to_path_buf());
+            }
+        }
+
+        inspection.inspect_regions(config, &regions);
+        inspection.inspect_level();
+        inspection.inspect_scoreboard();
+        inspection
+    }
+
+    fn inspect_regions(&mut self, config: &Config, regions: &[PathBuf]) {
+        let stats: Vec<_> = regions.par_iter().filter_map(|path| {
+            Region::load(path).and_then(|region| region.stats()).map_err(|err| {
+                log::warn!("could not read region ({err:#}) [{}]", path.display())
+            }).ok().map(|stats| (path, stats))
+        }).collect();
+
+        for (path, stats) in stats {
+            let RegionStats { chunks, corrupt, blocks, entities } = stats;
+            let dimension = self.dimensions.entry(entries::dimension(path).to_owned()).or_default();
+            let kind = path.parent().and_then(Path::file_name).unwrap_or_default();
+            *dimension.regions.entry(kind.to_string_lossy().into_owned()).or_default() += 1;
+            if kind == "region" {
+                dimension.chunks += chunks;
+            }
+            dimension.corrupt += corrupt;
+            merge_counts(&mut self.blocks, blocks);
+            merge_counts(&mut self.entities, entities);
+        }
+
+        // Chunks are decided once for all the region kinds sharing the same name
+        let names: HashSet<_> = regions.iter()
+            .map(|path| (entries::dimension(path), path.file_name().unwrap_or_default()))
+            .collect();
+        let dimensions: HashSet<_> = names.iter().map(|(dimension, _)| *dimension).collect();
+        let forced: HashMap<_, _> = dimensions.into_iter().map(|dimension| {
+            let forced = entries::read_forced_chunks(config, dimension).unwrap_or_else(|err| {
+                log::warn!("could not read forced chunks ({err:#}) [{}]", dimension.join("data/chunks.dat").display());
+                ChunkSet::default()
+            });
+            (dimension, forced)
+        }).collect();
+
+        let removable: Vec<_> = names.into_par_iter().filter_map(|(dimension, name)| {
+            let region = dimension.join("region").join(name);
+            let decisions = entries::decide_chunks(config, &region, &forced[dimension]).map_err(|err| {
+                log::warn!("could not decide chunks ({err:#}) [{}]", region.display())
+            }).ok()?;
+            Some((dimension, decisions.values().filter(|emptiness| emptiness.is_empty()).count()))
+        }).collect();
+
+        for (dimension, count) in removable {
+            self.dimensions.entry(dimension.to_owned()).or_default().removable += count;
+        }
+    }
+
+    fn inspect_level(&mut self) {
+        let path = Path::new("./level.dat");
+        match Level::load(path) {
+            Ok(level) => {
+                let datapacks = level.data.datapacks;
+                self.datapacks.extend(datapacks.enabled.into_iter().map(|name| (name, true)));
+                self.datapacks.extend(datapacks.disabled.into_iter().map(|name| (name, false)));
+            },
+            Err(err) => log::warn!("could not read level.dat ({err:#}) [{}]", path.display()),
+        }
+    }
+
+    fn inspect_scoreboard(&mut self) {
+        let path = Path::new("./data/scoreboard.dat");
+        if !path.is_file() {
+            return;
+        }
+        match Scoreboard::load(path) {
+            Ok(scoreboard) => self.scoreboard = Some(ScoreboardStats {
+                objectives: scoreboard.data.objectives.len(),
+                scores: scoreboard.data.scores.len(),
+                teams: scoreboard.data.teams.len(),
+            }),
+            Err(err) => log::warn!("could not read scoreboard ({err:#}) [{}]", path.display()),
+        }
+    }
+
+    pub fn print(&self, world: &Path) {
+        println!(
+            "  {} {} ({})",
+            console::style("Inspecting").green().bold(),
+            world.file_name().unwrap_or_default().to_string_lossy(),
+            world.display(),
+        );
+
+        print_title("Entries");
+        for (kind, (count, size)) in &self.entries {
+            println!("     {kind:<14} {count:>8} files {:>12}", format_size(*size));
+        }
+
+        print_title("Dimensions");
+        for (dimension, stats) in &self.dimensions {
+            let regions: Vec<_> = stats.regions.iter().map(|(kind, count)| format!("{count} {kind}")).collect();
+            println!("     {} ({})", dimension.display(), regions.join(", "));
+            println!(
+                "       {} chunks, {} removable by clean_chunks, {} corrupt",
+                stats.chunks, stats.removable, stats.corrupt,
+            );
+        }
+
+        print_title("Blocks (sections using them)");
+        for (name, count) in top(&self.blocks, TOP_BLOCKS) {
+            println!("     {name:<40} {count:>10}");
+        }
+
+        print_title("Entities");
+        for (name, count) in top(&self.entities, TOP_ENTITIES) {
+            println!("     {name:<40} {count:>10}");
+        }
+
+        print_title("Datapacks");
+        for (name, enabled) in &self.datapacks {
+            println!("     [{}] {name}", if *enabled { "x" } else { " " });
+        }
+
+        if let Some(scoreboard) = &self.scoreboard {
+            print_title("Scoreboard");
+            println!(
+                "     {} objectives, {} scores, {} teams",
+                scoreboard.objectives, scoreboard.scores, scoreboard.teams,
+            );
+        }
+    }
+}
+
+/// Walk every file of the world (in the working dir) regardless of the accepted entries.
+fn world_entries() -> Vec<Entry> {
+    let entries = Mutex::new(vec![]);
+    WalkBuilder::new("./")
+        .git_ignore(false)
+        .same_file_system(true)
+        .build_parallel()
+        .run(|| Box::new(|result| match result.ok().and_then(|e| Entry::guess(e.path())) {
+            None => WalkState::Continue,
+            Some(entry) => {
+                entries.lock().unwrap().push(entry);
+                WalkState::Skip
+            },
+        }));
+
+    entries.into_inner().unwrap()
+}
+
+/// Get the size of the entry on disk (including datapack directories and external chunk files).
+fn entry_size(entry: &Entry) -> u64 {
+    let mut files = match entry {
+        Entry::Region(region) => Region::external_files(region),
+        _ => vec![],
+    };
+    files.extend(WalkBuilder::new(entry.path()).same_file_system(true).build()
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path()));
+    files.iter().filter_map(|file| file.metadata().ok()).filter(|meta| meta.is_file()).map(|meta| meta.len()).sum()
+}
+
+fn merge_counts(counts: &mut HashMap<String, usize>, other: HashMap<String, usize>) {
+    for (key, count) in other {
+        *counts.entry(key).or_default() += count;
+    }
+}
+
+fn top(counts: &HashMap<String, usize>, limit: usize) -> Vec<(&String, &usize)> {
+    let mut top: Vec<_> = counts.iter().collect();
+    top.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    top.truncate(limit);
+    top
+}
+
+fn print_title(title: &str) {
+    println!("   {}", console::style(title).cyan().bold());
+}
+
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} {}", units[0]),
+        _ => format!("{size:.1} {}", units[unit]),
+    }
+}
@@ -1,17 +1,25 @@
+pub mod diff;
 pub mod entries;
 pub mod formats;
+pub mod inspect;
 pub mod storage;
 pub mod utils;
 
-pub use config::Config;
+pub use config::{
+    Area, BiomeOverride, BlockEntityFilter, Config, CorruptPolicy, DataSettings, Difficulty,
+    EntityFilter, GameType, LevelSettings, NbtPatch, PlayerFiles, PlayerSettings, Recompression,
+    Seed, StripSettings,
+};
 
 mod config;
 
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Instant;
 
-use entries::{Entry, Packageable};
+use entries::{Entry, Packageable, SiblingRegions};
+use formats::ChunkSet;
 use ignore::{WalkBuilder, WalkState};
 use indicatif::{ProgressBar, ProgressStyle};
 use path_absolutize::Absolutize;
@@ -40,15 +48,30 @@ pub struct Packager<S: Storage> {
     world: PathBuf,
     target: S,
     progress: ProgressBar,
+    /// Map ids referenced in the world, scanned before packaging when unreferenced maps are removed.
+    maps: OnceLock<Option<HashSet<i32>>>,
+    forced_chunks: Cache<ChunkSet>,
+    sibling_regions: Cache<OnceLock<SiblingRegions>>,
 }
 
+/// Values computed once per path (e.g. per dimension) while packaging.
+type Cache<T> = Mutex<HashMap<PathBuf, Arc<T>>>;
+
 impl<S: Storage> Packager<S> {
     pub fn new(config: Config, world: PathBuf, target: S) -> Self {
         let tmpl = format!("   {}", PB_TEMPLATE);
         let style = ProgressStyle::with_template(&tmpl).unwrap().progress_chars("=>-");
         let progress = ProgressBar::new(0).with_style(style).with_prefix("Progress");
 
-        Self { config, world, target, progress }
+        Self {
+            config,
+            world,
+            target,
+            progress,
+            maps: OnceLock::new(),
+            forced_chunks: Mutex::default(),
+            sibling_regions: Mutex::default(),
+        }
     }
 
     pub fn run(&self) {
@@ -57,6 +80,12 @@ impl<S: Storage> Packager<S> {
         let mut entries = vec![];
         entries.extend(self.world_entries());
         entries.extend(self.extra_entries());
+        self.check_set_storages(&entries);
+
+        // Scanned once before the parallel packaging (entries only read the result)
+        if self.config.data.remove_unreferenced_maps {
+            self.maps.get_or_init(|| self.scan_referenced_maps());
+        }
 
         self.package(&entries)
     }
@@ -68,7 +97,7 @@ impl<S: Storage> Packager<S> {
 
         entries.par_iter().for_each(|entry| {
             entry.package(self).unwrap_or_else(|err| self.progress.suspend(|| {
-                log::warn!("{err} [{}]", entry.path().display())
+                log::warn!("{err:#} [{}]", entry.path().display())
             }));
             self.progress.inc(1);
         });
@@ -80,7 +109,9 @@ impl<S: Storage> Packager<S> {
     pub fn extra_entries(&self) -> Vec<Entry> {
         let mut entries = vec![];
         entries.extend(self.config.extra_entries.iter().map(|e| Entry::Extra(e.to_owned())));
-        entries.extend(self.config.resourcepack.to_owned().map(|e| Entry::Resourcepack(e.into())));
+        if !self.config.resourcepack.is_empty() {
+            entries.push(Entry::Resourcepack(self.config.resourcepack.to_owned().into()));
+        }
 
         entries
     }
@@ -1,8 +1,12 @@
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use fastnbt::Value;
 use log::{Level, LevelFilter};
+use mcwpack::diff::WorldDiff;
+use mcwpack::formats::{self, snbt, NbtChange, NbtFormat};
+use mcwpack::inspect::Inspection;
 use mcwpack::utils::*;
 use mcwpack::Config;
 use mcwpack::Context;
@@ -16,6 +20,8 @@ const DEFAULT_CONFIG: &str = "mcwpack.yaml";
     author = "Aksiome",
 )]
 pub struct Opts {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[clap(value_name = "WORLD_PATH")]
     world: Option<PathBuf>,
     /// Set the output zip
@@ -28,19 +34,62 @@ pub struct Opts {
     #[arg(short, value_name = "CONFIG_FILE")]
     config: Option<PathBuf>,
     /// Show debug trace
-    #[arg(short, long, conflicts_with = "quiet")]
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
     verbose: bool,
     /// Silence warning
-    #[arg(short, long, conflicts_with = "verbose")]
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
     quiet: bool,
     /// Force colorization
-    #[arg(long)]
+    #[arg(long, global = true)]
     colors: bool,
     /// Ignore prompts
     #[arg(long)]
     noprompt: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Print statistics about a world without modifying it
+    Inspect {
+        #[clap(value_name = "WORLD_PATH")]
+        world: PathBuf,
+        /// Use the given config file (defaults to the world config if any)
+        #[arg(short, value_name = "CONFIG_FILE")]
+        config: Option<PathBuf>,
+    },
+    /// Compare two worlds (directories or zips) file by file and chunk by chunk
+    Diff {
+        #[clap(value_name = "OLD_WORLD")]
+        old: PathBuf,
+        #[clap(value_name = "NEW_WORLD")]
+        new: PathBuf,
+    },
+    /// Dump or compare nbt files (gzip, zlib or uncompressed)
+    Nbt {
+        #[command(subcommand)]
+        command: NbtCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum NbtCommand {
+    /// Print a nbt file as snbt
+    Dump {
+        #[clap(value_name = "FILE")]
+        file: PathBuf,
+        /// Print as json instead
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the differences between two nbt files
+    Diff {
+        #[clap(value_name = "OLD_FILE")]
+        old: PathBuf,
+        #[clap(value_name = "NEW_FILE")]
+        new: PathBuf,
+    },
+}
+
 fn main() {
     let opts = Opts::parse();
 
@@ -69,6 +118,71 @@ fn main() {
         })
     }).filter(Some("mcwpack"), verbosity).init();
 
+    match opts.command {
+        Some(Command::Inspect { ref world, ref config }) => inspect(world, config.as_deref()),
+        Some(Command::Diff { ref old, ref new }) => diff(old, new),
+        Some(Command::Nbt { command: NbtCommand::Dump { ref file, json } }) => nbt_dump(file, json),
+        Some(Command::Nbt { command: NbtCommand::Diff { ref old, ref new } }) => nbt_diff(old, new),
+        None => package(opts),
+    }
+}
+
+fn inspect(world: &Path, config: Option<&Path>) {
+    let world = world.canonicalize().unwrap_or_else(|err| {
+        log::error!("the world path is not valid ({})", err);
+        std::process::exit(1);
+    });
+
+    let config = match config.map(Path::to_owned).unwrap_or_else(|| world.join(DEFAULT_CONFIG)) {
+        path if config.is_some() || path.is_file() => Config::load(&path, true).unwrap_or_else(|| std::process::exit(1)),
+        _ => Config::default(),
+    };
+
+    Inspection::run(&config, &world).print(&world);
+}
+
+fn diff(old: &Path, new: &Path) {
+    match WorldDiff::run(old, new) {
+        Ok(diff) => diff.print(old, new),
+        Err(err) => {
+            log::error!("{err:#}");
+            std::process::exit(1);
+        },
+    }
+}
+
+fn load_nbt(file: &Path) -> Value {
+    Value::load(file).unwrap_or_else(|err| {
+        log::error!("could not read nbt file ({err:#}) [{}]", file.display());
+        std::process::exit(1);
+    })
+}
+
+fn nbt_dump(file: &Path, json: bool) {
+    let value = load_nbt(file);
+    match json {
+        true => println!("{:#}", formats::to_json(&value)),
+        false => println!("{}", snbt::to_string_pretty(&value)),
+    }
+}
+
+fn nbt_diff(old: &Path, new: &Path) {
+    for change in formats::diff(&load_nbt(old), &load_nbt(new)) {
+        match change {
+            NbtChange::Added(path, value) => {
+                println!("{}", console::style(format!("+ {path}: {}", snbt::to_string(&value))).green())
+            },
+            NbtChange::Removed(path, value) => {
+                println!("{}", console::style(format!("- {path}: {}", snbt::to_string(&value))).red())
+            },
+            NbtChange::Changed(path, old, new) => println!("{}", console::style(format!(
+                "~ {path}: {} -> {}", snbt::to_string(&old), snbt::to_string(&new),
+            )).yellow()),
+        }
+    }
+}
+
+fn package(opts: Opts) {
     let world = opts.world.to_owned().map(|p| p.canonicalize().unwrap_or_else(|err| {
         log::error!("the world path is not valid ({})", err);
         std::process::exit(1);
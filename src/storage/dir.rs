@@ -1,8 +1,9 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use ignore::WalkBuilder;
 
-use super::{FilesystemStorage, Storage};
+use super::{FilesystemStorage, ReadableStorage, Storage};
 
 pub struct DirStorage {
     root: PathBuf,
@@ -34,3 +35,21 @@ impl Storage for DirStorage {
         Ok(std::fs::write(file, contents)?)
     }
 }
+
+impl ReadableStorage for DirStorage {
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        for entry in WalkBuilder::new(&self.root).same_file_system(true).build() {
+            let entry = entry?;
+            if entry.path().is_file() {
+                files.push(entry.path().strip_prefix(&self.root)?.to_owned());
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn read(&self, file: &Path) -> Result<Vec<u8>> {
+        Ok(std::fs::read(self.root.join(file))?)
+    }
+}
@@ -1,10 +1,10 @@
 pub use self::dir::DirStorage;
-pub use self::zip::ZipStorage;
+pub use self::zip::{ZipReader, ZipStorage};
 
 mod dir;
 mod zip;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use ignore::WalkBuilder;
@@ -37,3 +37,42 @@ pub trait Storage: Send + Sync {
         Ok(())
     }
 }
+
+pub trait ReadableStorage: Send + Sync {
+    /// List the files contained in the storage.
+    fn files(&self) -> Result<Vec<PathBuf>>;
+
+    /// Read a file from the storage.
+    fn read(&self, file: &Path) -> Result<Vec<u8>>;
+}
+
+/// Open a directory or a zip archive as a readable storage.
+pub fn open(path: &Path) -> Result<Box<dyn ReadableStorage>> {
+    Ok(match path.is_dir() {
+        true => Box::new(DirStorage::new(path)),
+        false => Box::new(ZipReader::open(path)?),
+    })
+}
+
+/// Storage keeping the written files in memory (to check the packaged files in tests).
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryStorage {
+    pub files: std::sync::Mutex<std::collections::BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.write(to, &std::fs::read(from)?)
+    }
+
+    fn write(&self, file: &Path, contents: &[u8]) -> Result<()> {
+        self.files.lock().unwrap().insert(file.to_owned(), contents.to_vec());
+        Ok(())
+    }
+}
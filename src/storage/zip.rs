@@ -1,14 +1,14 @@
 use std::fs::File;
-use std::io::{Cursor, Seek, Write};
+use std::io::{Cursor, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 use anyhow::Result;
 use zip::result::ZipResult;
 use zip::write::FileOptions;
-use zip::ZipWriter;
+use zip::{ZipArchive, ZipWriter};
 
-use super::{FilesystemStorage, InMemoryStorage, Storage};
+use super::{FilesystemStorage, InMemoryStorage, ReadableStorage, Storage};
 
 pub struct ZipStorage<W: Write + Seek> {
     path: Option<PathBuf>,
@@ -60,3 +60,31 @@ impl<W: Write + Seek + Send + Sync> Storage for ZipStorage<W> {
         Ok(writer.write_all(contents)?)
     }
 }
+
+pub struct ZipReader {
+    archive: Mutex<ZipArchive<File>>,
+}
+
+impl ZipReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self { archive: Mutex::new(ZipArchive::new(File::open(path)?)?) })
+    }
+}
+
+impl ReadableStorage for ZipReader {
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.archive.lock().unwrap().file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(PathBuf::from)
+            .collect())
+    }
+
+    fn read(&self, file: &Path) -> Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(&file.to_string_lossy().replace('\\', "/"))?;
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut contents)?;
+
+        Ok(contents)
+    }
+}